@@ -1,65 +1,570 @@
 //! Package scan caching for faster repeated resolution.
+//!
+//! Every package path gets its own cache entry, keyed by the path alone:
+//! entries hold packages as published, before the config's filters and
+//! overrides apply, so editing the config never invalidates them.  Inside
+//! an entry each family directory (`{name}/`) and each flat
+//! `{name}-{version}.yaml` file is fingerprinted separately, so a new
+//! release only re-reads the family directory it landed in.
+//!
+//! A package path may also carry a prebuilt repository index
+//! (`.anvil-index.json`, written by `anvil index build`).  Its entries are
+//! fingerprinted the same way, so a scan takes every entry whose fingerprint
+//! still matches from the index and only parses what changed since.
 
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use crate::package::Package;
+use crate::package::{Package, PackageRequest};
 
-/// Cached package scan results.
-#[derive(Serialize, Deserialize)]
-struct ScanCache {
-    /// Fingerprint of the package paths at the time of caching.
+/// Cached scan of a single package path.
+#[derive(Default, Serialize, Deserialize)]
+struct PathCache {
+    /// The package path this entry describes.
+    path: PathBuf,
+    /// Flat package files: file name -> entry.
+    #[serde(default)]
+    files: HashMap<String, CachedEntry>,
+    /// Family directories: directory name -> entry.
+    #[serde(default)]
+    families: HashMap<String, CachedEntry>,
+}
+
+/// Packages loaded from one flat file or family directory.
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedEntry {
+    /// Fingerprint of the file / directory when it was read.
     fingerprint: u64,
-    /// The cached package data: name -> version -> Package.
-    packages: HashMap<String, HashMap<String, Package>>,
+    /// Packages it contained (empty if they failed to load).
+    packages: Vec<Package>,
+}
+
+impl PathCache {
+    fn packages(&self) -> impl Iterator<Item = &Package> {
+        self.files
+            .values()
+            .chain(self.families.values())
+            .flat_map(|e| e.packages.iter())
+    }
+}
+
+/// Every file anvil writes (cache entries and repository indexes) carries
+/// a format version.  A file with another version, or none, is ignored
+/// rather than mis-read, so older and newer binaries can share a cache.
+/// `T` is flattened next to the version, so it must serialize as a map.
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    #[serde(flatten)]
+    data: T,
+}
+
+/// Just the version of a `Versioned` file, read before the rest.
+#[derive(Deserialize)]
+struct FormatHeader {
+    version: Option<u32>,
 }
 
-/// Return the cache file path (`~/.cache/anvil/packages.json`).
-fn cache_path() -> Option<PathBuf> {
-    directories::ProjectDirs::from("", "", "anvil").map(|d| d.cache_dir().join("packages.json"))
+/// Current format of the files in the cache directory.  Bump when
+/// `PathCache`, `ResolveEntry` or `Package` change incompatibly.
+const CACHE_VERSION: u32 = 3;
+
+/// File name of the repository index inside a package path.
+pub const INDEX_FILE: &str = ".anvil-index.json";
+
+/// Current repository index format.  Bump when `PathCache` or `Package`
+/// change incompatibly.
+const INDEX_VERSION: u32 = 3;
+
+/// Read a versioned file.  `Ok(None)` when it doesn't exist; `Err` with the
+/// reason when it exists but can't be used.
+fn read_versioned<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    expected: u32,
+) -> std::result::Result<Option<T>, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+    let header: FormatHeader = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    match header.version {
+        Some(v) if v == expected => {}
+        Some(v) => return Err(format!("format {} (expected {})", v, expected)),
+        None => return Err(format!("no format version (expected {})", expected)),
+    }
+    let file: Versioned<T> = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    Ok(Some(file.data))
 }
 
-/// Compute a fingerprint of all package paths by hashing directory entries
-/// and their mtimes.  Walks two levels deep to cover both flat files and
-/// nested `{name}/{version}/package.yaml` layouts.
+/// Write `data` to `path` atomically with a format version.  The JSON goes
+/// to a temp file in the same directory which is then renamed over `path`,
+/// so readers see the old file or the new one, never a torn one.
 ///
-/// The `config_salt` is hashed in so that config changes (e.g. different
-/// filters or package paths) invalidate the cache.
-pub fn compute_fingerprint(package_paths: &[PathBuf], config_salt: &str) -> u64 {
+/// Writers serialize on an advisory lock on a `.lock` file next to `path`.
+/// With `wait` false a writer that finds the lock taken skips its write
+/// (the holder is writing the same data) and returns `false`.
+///
+/// Nothing is left next to `path` afterwards (it may sit in a shared
+/// package root): on Unix the holder removes the lock file before
+/// releasing it, and temp files of writers killed mid-write are swept up.
+fn write_versioned<T: Serialize>(path: &Path, version: u32, data: &T, wait: bool) -> Result<bool> {
+    use std::io::Write;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+
+    let lock_path = path.with_extension("lock");
+    let lock = loop {
+        let lock = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open lock file {}", lock_path.display()))?;
+        if wait {
+            lock.lock()?;
+        } else {
+            match lock.try_lock() {
+                Ok(()) => {}
+                Err(std::fs::TryLockError::WouldBlock) => {
+                    debug!("{:?} is being written by another process, skipping", path);
+                    return Ok(false);
+                }
+                Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+        // The previous holder may have removed the file while we waited;
+        // the lock only counts if it is on the file now at `lock_path`.
+        if is_same_file(&lock, &lock_path) {
+            break lock;
+        }
+    };
+
+    let prefix = format!(".{}.", path.file_name().unwrap_or_default().to_string_lossy());
+    remove_stale_temp_files(dir, &prefix);
+
+    let mut tmp = tempfile::Builder::new()
+        .prefix(&prefix)
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+    serde_json::to_writer(&mut tmp, &Versioned { version, data })?;
+    tmp.flush()?;
+    tmp.persist(path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+
+    if cfg!(unix) {
+        let _ = std::fs::remove_file(&lock_path);
+    }
+    drop(lock);
+    Ok(true)
+}
+
+/// Whether the open `file` is the one currently at `path`.
+#[cfg(unix)]
+fn is_same_file(file: &std::fs::File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Without inode numbers to compare, the lock file is never removed (see
+/// `write_versioned`), so it can't have been replaced.
+#[cfg(not(unix))]
+fn is_same_file(_file: &std::fs::File, _path: &Path) -> bool {
+    true
+}
+
+/// Remove temp files named `{prefix}*.tmp` in `dir`.  Only the holder of the
+/// write lock creates them, so any found by the holder belong to a writer
+/// that died before renaming its file into place.
+fn remove_stale_temp_files(dir: &Path, prefix: &str) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(prefix) && name.ends_with(".tmp") {
+            debug!("Removing leftover temp file {:?}", entry.path());
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Return the default cache directory (`~/.cache/anvil`).
+pub fn default_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "anvil").map(|d| d.cache_dir().to_path_buf())
+}
+
+/// Subdirectory of the cache directory holding per-path scan entries.
+const SCAN_SUBDIR: &str = "scan";
+
+/// Single-file cache written by older versions; only ever deleted.
+const LEGACY_FILE: &str = "packages.json";
+
+/// Return the cache file for one package path.
+pub fn entry_file(cache_dir: &Path, package_path: &Path) -> PathBuf {
     let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    config_salt.hash(&mut hasher);
+    CACHE_VERSION.hash(&mut hasher);
+    package_path.hash(&mut hasher);
+    cache_dir
+        .join(SCAN_SUBDIR)
+        .join(format!("{:016x}.json", hasher.finish()))
+}
+
+/// Scan `package_paths`, reusing cached results for every family directory
+/// and flat file whose fingerprint is unchanged.  When `refresh` is true
+/// the cache is ignored (but still rewritten).  With no `cache_dir` nothing
+/// is cached.
+pub fn scan(
+    cache_dir: Option<&Path>,
+    package_paths: &[PathBuf],
+    refresh: bool,
+) -> Result<HashMap<String, HashMap<String, Package>>> {
+    let mut packages: HashMap<String, HashMap<String, Package>> = HashMap::new();
+    let mut reread = 0;
 
     for base in package_paths {
-        if !base.exists() {
-            continue;
+        debug!("Scanning packages in {:?}", base);
+        let cached = match cache_dir {
+            Some(dir) if !refresh => load(dir, base),
+            _ => None,
+        };
+        let index = if refresh { None } else { load_index(base) };
+        let (entry, stats) = scan_path(base, cached, index)?;
+        reread += stats.reread;
+        if stats.indexed > 0 {
+            info!("Took {} entries from the repository index in {:?}", stats.indexed, base);
         }
-        base.hash(&mut hasher);
-        hash_dir_entries(base, &mut hasher);
-
-        // One level deeper for nested packages
-        if let Ok(entries) = std::fs::read_dir(base) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_dir() {
-                    hash_dir_entries(&path, &mut hasher);
-
-                    // Check package.yaml files inside version dirs
-                    if let Ok(sub_entries) = std::fs::read_dir(&path) {
-                        for sub in sub_entries.flatten() {
-                            if sub.path().is_dir() {
-                                let pkg_file = sub.path().join("package.yaml");
-                                if pkg_file.exists() {
-                                    hash_file_mtime(&pkg_file, &mut hasher);
-                                }
-                            }
-                        }
-                    }
+
+        if let Some(dir) = cache_dir.filter(|_| stats.changed()) {
+            if let Err(e) = save(dir, &entry) {
+                debug!("Failed to save cache for {:?}: {}", base, e);
+            }
+        }
+
+        for pkg in entry.packages() {
+            packages
+                .entry(pkg.name.clone())
+                .or_default()
+                .insert(pkg.version.clone(), pkg.clone());
+        }
+    }
+
+    if reread == 0 {
+        info!("Using cached package scan");
+    } else {
+        info!("Re-read {} package file(s) / family dir(s)", reread);
+    }
+    Ok(packages)
+}
+
+/// Where the definitions of one package family live on disk, as found by
+/// `list_families` without parsing anything.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Source {
+    /// The package path the entry was listed from.
+    base: PathBuf,
+    /// File or directory name inside `base`.
+    entry: String,
+    /// True for a flat `{name}-{version}.yaml` file, false for a family dir.
+    flat: bool,
+}
+
+/// List the package families in `package_paths` by name, without reading
+/// any package file.  Family directories are keyed by their directory name,
+/// flat files by the name part of `{name}-{version}.yaml`.
+pub fn list_families(package_paths: &[PathBuf]) -> Result<HashMap<String, Vec<Source>>> {
+    let mut families: HashMap<String, Vec<Source>> = HashMap::new();
+
+    for base in package_paths {
+        for entry in std::fs::read_dir(base)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+
+            let (name, flat) = if path.is_dir() {
+                (file_name.clone(), false)
+            } else {
+                let Some(stem) = file_name
+                    .strip_suffix(".yaml")
+                    .or_else(|| file_name.strip_suffix(".yml"))
+                else {
+                    continue;
+                };
+                let name = match PackageRequest::parse(stem) {
+                    Ok(req) => req.name,
+                    Err(_) => stem.to_string(),
+                };
+                (name, true)
+            };
+
+            families.entry(name).or_default().push(Source {
+                base: base.clone(),
+                entry: file_name,
+                flat,
+            });
+        }
+    }
+
+    Ok(families)
+}
+
+/// Load the packages behind `sources`, reusing cached entries whose
+/// fingerprint still matches.  Doesn't write the cache: only full scans do.
+pub fn load_sources(
+    cache_dir: Option<&Path>,
+    sources: &[Source],
+    refresh: bool,
+) -> Vec<Package> {
+    let mut by_base: HashMap<&Path, [Option<PathCache>; 2]> = HashMap::new();
+    let mut packages = Vec::new();
+
+    for source in sources {
+        let path = source.base.join(&source.entry);
+        let fingerprint = source_fingerprint(source);
+
+        // Local cache first, then the repository index.
+        let seeds = by_base.entry(source.base.as_path()).or_insert_with(|| {
+            if refresh {
+                return [None, None];
+            }
+            let cached = cache_dir.and_then(|dir| load(dir, &source.base));
+            [cached, load_index(&source.base)]
+        });
+        let hit = seeds.iter().flatten().find_map(|c| {
+            let entries = if source.flat { &c.files } else { &c.families };
+            entries.get(&source.entry).filter(|e| e.fingerprint == fingerprint)
+        });
+
+        match hit {
+            Some(entry) => packages.extend(entry.packages.iter().cloned()),
+            None if source.flat => packages.extend(load_flat(&path)),
+            None => packages.extend(load_family(&path)),
+        }
+    }
+
+    packages
+}
+
+/// Fingerprint one source the same way the scan cache does.
+fn source_fingerprint(source: &Source) -> u64 {
+    let path = source.base.join(&source.entry);
+    if source.flat {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        hash_file_mtime(&path, &mut hasher);
+        hasher.finish()
+    } else {
+        family_fingerprint(&path)
+    }
+}
+
+/// Fingerprint every source of a family, in a stable order.
+pub fn fingerprint_sources(sources: &[Source]) -> Vec<(Source, u64)> {
+    let mut fingerprints: Vec<(Source, u64)> = sources
+        .iter()
+        .map(|s| (s.clone(), source_fingerprint(s)))
+        .collect();
+    fingerprints.sort();
+    fingerprints
+}
+
+/// Subdirectory of the cache directory holding cached resolves.
+const RESOLVE_SUBDIR: &str = "resolve";
+
+/// A cached `Resolver::resolve` result.
+#[derive(Serialize, Deserialize)]
+pub struct ResolveEntry {
+    /// Every family the resolve looked up, with the fingerprint of each of
+    /// its sources at the time.  Any difference invalidates the entry.
+    pub families: std::collections::BTreeMap<String, Vec<(Source, u64)>>,
+    /// The resolved packages, dependencies first.
+    pub packages: Vec<Package>,
+}
+
+fn resolve_file(cache_dir: &Path, key: u64) -> PathBuf {
+    cache_dir.join(RESOLVE_SUBDIR).join(format!("{:016x}.json", key))
+}
+
+/// Load the cached resolve stored under `key`, if any.  The caller checks
+/// that its families are unchanged.
+pub fn load_resolve(cache_dir: &Path, key: u64) -> Option<ResolveEntry> {
+    let path = resolve_file(cache_dir, key);
+    read_versioned(&path, CACHE_VERSION).unwrap_or_else(|e| {
+        debug!("Ignoring resolve cache {:?}: {}", path, e);
+        None
+    })
+}
+
+/// Store a resolve result under `key`.
+pub fn save_resolve(cache_dir: &Path, key: u64, entry: &ResolveEntry) -> Result<()> {
+    let path = resolve_file(cache_dir, key);
+    if write_versioned(&path, CACHE_VERSION, entry, false)? {
+        debug!("Saved resolve cache to {:?}", path);
+    }
+    Ok(())
+}
+
+/// Number of cached resolves in `cache_dir`.
+pub fn resolve_count(cache_dir: &Path) -> usize {
+    std::fs::read_dir(cache_dir.join(RESOLVE_SUBDIR))
+        .map(|dir| {
+            dir.flatten()
+                .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// What `scan_path` had to do.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ScanStats {
+    /// Files / family directories parsed afresh.
+    reread: usize,
+    /// Cached files / family directories that no longer exist.
+    removed: usize,
+    /// Files / family directories taken from the repository index because
+    /// the cache had no valid entry for them.
+    indexed: usize,
+}
+
+impl ScanStats {
+    fn changed(&self) -> bool {
+        self.reread > 0 || self.removed > 0 || self.indexed > 0
+    }
+}
+
+/// Scan one package path, reusing whatever in `cached` is still valid and
+/// falling back to `index` before parsing anything.
+fn scan_path(
+    base: &Path,
+    cached: Option<PathCache>,
+    index: Option<PathCache>,
+) -> Result<(PathCache, ScanStats)> {
+    let mut old = cached.unwrap_or_default();
+    let mut index = index.unwrap_or_default();
+    let mut fresh = PathCache {
+        path: base.to_path_buf(),
+        ..PathCache::default()
+    };
+    let mut stats = ScanStats::default();
+
+    for entry in std::fs::read_dir(base)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if path.is_file() {
+            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            if ext != "yaml" && ext != "yml" {
+                continue;
+            }
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            hash_file_mtime(&path, &mut hasher);
+            let fingerprint = hasher.finish();
+
+            let cached_entry = old.files.remove(&name).filter(|e| e.fingerprint == fingerprint);
+            let indexed = || {
+                let hit = index.files.remove(&name).filter(|e| e.fingerprint == fingerprint);
+                stats.indexed += hit.is_some() as usize;
+                hit
+            };
+            let entry = cached_entry.or_else(indexed).unwrap_or_else(|| {
+                stats.reread += 1;
+                CachedEntry {
+                    fingerprint,
+                    packages: load_flat(&path).into_iter().collect(),
                 }
+            });
+            fresh.files.insert(name, entry);
+        } else if path.is_dir() {
+            let fingerprint = family_fingerprint(&path);
+
+            let cached_entry = old.families.remove(&name).filter(|e| e.fingerprint == fingerprint);
+            let indexed = || {
+                let hit = index.families.remove(&name).filter(|e| e.fingerprint == fingerprint);
+                stats.indexed += hit.is_some() as usize;
+                hit
+            };
+            let entry = cached_entry.or_else(indexed).unwrap_or_else(|| {
+                stats.reread += 1;
+                CachedEntry {
+                    fingerprint,
+                    packages: load_family(&path),
+                }
+            });
+            fresh.families.insert(name, entry);
+        }
+    }
+
+    stats.removed = old.files.len() + old.families.len();
+    Ok((fresh, stats))
+}
+
+/// Load a flat `{name}-{version}.yaml` package file.
+fn load_flat(path: &Path) -> Option<Package> {
+    match Package::load_from_file(path, None) {
+        Ok(pkg) => {
+            debug!("Loaded package (flat): {}-{}", pkg.name, pkg.version);
+            Some(pkg)
+        }
+        Err(e) => {
+            warn!("Failed to load package {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Load every `{version}/package.yaml` inside a family directory.
+fn load_family(dir: &Path) -> Vec<Package> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        warn!("Failed to read package directory {:?}", dir);
+        return Vec::new();
+    };
+
+    let mut packages = Vec::new();
+    for version_dir in entries.flatten().map(|e| e.path()) {
+        if !version_dir.is_dir() || !version_dir.join("package.yaml").exists() {
+            continue;
+        }
+        match Package::load(&version_dir) {
+            Ok(pkg) => {
+                debug!("Loaded package (nested): {}-{}", pkg.name, pkg.version);
+                packages.push(pkg);
+            }
+            Err(e) => {
+                warn!("Failed to load package {:?}: {}", version_dir, e);
+            }
+        }
+    }
+    packages
+}
+
+/// Fingerprint a family directory: its own entries plus the
+/// `package.yaml` inside each version directory, so in-place edits that
+/// don't touch the directory mtime are still caught.
+fn family_fingerprint(dir: &Path) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hash_dir_entries(dir, &mut hasher);
+
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        let mut version_dirs: Vec<PathBuf> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect();
+        version_dirs.sort();
+        for version_dir in version_dirs {
+            let pkg_file = version_dir.join("package.yaml");
+            if pkg_file.exists() {
+                hash_file_mtime(&pkg_file, &mut hasher);
             }
         }
     }
@@ -100,8 +605,10 @@ fn hash_dir_entries(dir: &Path, hasher: &mut impl Hasher) {
     }
 }
 
+/// Hash a file's name, mtime and size.  Only the file name goes in, not the
+/// full path, so an index built on one mount still matches on another.
 fn hash_file_mtime(path: &Path, hasher: &mut impl Hasher) {
-    path.hash(hasher);
+    path.file_name().hash(hasher);
     if let Ok(meta) = std::fs::metadata(path) {
         if let Ok(mtime) = meta.modified() {
             mtime
@@ -114,50 +621,429 @@ fn hash_file_mtime(path: &Path, hasher: &mut impl Hasher) {
     }
 }
 
-/// Try to load cached packages.  Returns `Some(packages)` if the cache
-/// is valid (fingerprint matches), `None` otherwise.
-pub fn load(package_paths: &[PathBuf], config_salt: &str) -> Option<HashMap<String, HashMap<String, Package>>> {
-    let path = cache_path()?;
-    if !path.exists() {
+/// Load the cached scan of one package path, if any.
+fn load(cache_dir: &Path, package_path: &Path) -> Option<PathCache> {
+    let path = entry_file(cache_dir, package_path);
+    let cached: PathCache = read_versioned(&path, CACHE_VERSION)
+        .unwrap_or_else(|e| {
+            debug!("Ignoring cache entry {:?}: {}", path, e);
+            None
+        })?;
+    if cached.path != package_path {
+        debug!("Cache entry {:?} belongs to another path, ignoring", path);
         return None;
     }
+    Some(cached)
+}
 
-    let content = std::fs::read_to_string(&path).ok()?;
-    let cached: ScanCache = serde_json::from_str(&content).ok()?;
-
-    let current_fp = compute_fingerprint(package_paths, config_salt);
-    if cached.fingerprint != current_fp {
-        debug!("Cache fingerprint mismatch, re-scanning");
-        return None;
+/// Save the scan of one package path.
+fn save(cache_dir: &Path, entry: &PathCache) -> Result<()> {
+    let path = entry_file(cache_dir, &entry.path);
+    if write_versioned(&path, CACHE_VERSION, entry, false)? {
+        debug!("Saved package cache to {:?}", path);
     }
+    Ok(())
+}
 
-    info!("Using cached package scan");
-    Some(cached.packages)
+/// The last cached scan of a package path that can't be reached now, with
+/// the time it was written.  Used as an offline fallback.
+pub fn load_offline(
+    cache_dir: &Path,
+    package_path: &Path,
+) -> Option<(Vec<Package>, Option<std::time::SystemTime>)> {
+    let cached = load(cache_dir, package_path)?;
+    let written = std::fs::metadata(entry_file(cache_dir, package_path))
+        .and_then(|m| m.modified())
+        .ok();
+    Some((cached.packages().cloned().collect(), written))
 }
 
-/// Save the scanned packages to the cache file.
-pub fn save(
-    package_paths: &[PathBuf],
-    config_salt: &str,
-    packages: &HashMap<String, HashMap<String, Package>>,
-) -> Result<()> {
-    let path = match cache_path() {
-        Some(p) => p,
-        None => return Ok(()), // No cache dir available, skip silently
+/// Format the time since `then` as the largest whole unit ("3h").
+pub fn format_age(then: std::time::SystemTime) -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(then)
+        .unwrap_or_default()
+        .as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Load the repository index of `package_path`, if it has a usable one.
+/// Package roots are rebased when the index was built under another mount.
+fn load_index(package_path: &Path) -> Option<PathCache> {
+    let file = package_path.join(INDEX_FILE);
+    let mut scan: PathCache = match read_versioned(&file, INDEX_VERSION) {
+        Ok(index) => index?,
+        Err(e) => {
+            warn!("Ignoring repository index {:?}: {}", file, e);
+            return None;
+        }
     };
 
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent)?;
+    if scan.path != package_path {
+        debug!("Rebasing repository index from {:?} to {:?}", scan.path, package_path);
+        for entry in scan.files.values_mut().chain(scan.families.values_mut()) {
+            for pkg in &mut entry.packages {
+                if let Ok(rel) = pkg.root.strip_prefix(&scan.path) {
+                    pkg.root = package_path.join(rel);
+                }
+                if let Ok(rel) = pkg.definition.strip_prefix(&scan.path) {
+                    pkg.definition = package_path.join(rel);
+                }
+            }
+        }
+        scan.path = package_path.to_path_buf();
     }
+    Some(scan)
+}
+
+/// What `write_index` produced.
+#[derive(Debug)]
+pub struct IndexSummary {
+    /// The index file written.
+    pub file: PathBuf,
+    /// Packages it holds.
+    pub packages: usize,
+    /// Files / family directories parsed to build it.
+    pub reread: usize,
+}
+
+/// Write the repository index for `package_path`.  With `rebuild` every
+/// package is parsed afresh; otherwise entries of the existing index whose
+/// fingerprint still matches are kept.
+pub fn write_index(package_path: &Path, rebuild: bool) -> Result<IndexSummary> {
+    let base = package_path
+        .canonicalize()
+        .with_context(|| format!("Package path not found: {}", package_path.display()))?;
+    let previous = if rebuild { None } else { load_index(&base) };
+    let (scan, stats) = scan_path(&base, previous, None)?;
+
+    let file = base.join(INDEX_FILE);
+    write_versioned(&file, INDEX_VERSION, &scan, true)
+        .with_context(|| format!("Failed to write {}", file.display()))?;
+    debug!("Wrote repository index {:?}", file);
+
+    Ok(IndexSummary {
+        file,
+        packages: scan.packages().count(),
+        reread: stats.reread,
+    })
+}
 
-    let cached = ScanCache {
-        fingerprint: compute_fingerprint(package_paths, config_salt),
-        packages: packages.clone(),
+/// Summary of one cache entry on disk, for `anvil cache info`.
+#[derive(Debug)]
+pub struct EntryInfo {
+    /// The package path it caches.
+    pub path: PathBuf,
+    /// Size of the cache file in bytes.
+    pub size: u64,
+    /// When the cache file was last written.
+    pub modified: Option<std::time::SystemTime>,
+    /// Combined fingerprint of every family directory and flat file.
+    pub fingerprint: u64,
+    pub families: usize,
+    pub files: usize,
+    pub packages: usize,
+}
+
+/// Describe every readable scan entry in `cache_dir`, sorted by package path.
+pub fn entries(cache_dir: &Path) -> Vec<EntryInfo> {
+    let Ok(dir) = std::fs::read_dir(cache_dir.join(SCAN_SUBDIR)) else {
+        return Vec::new();
     };
 
-    let content = serde_json::to_string(&cached)?;
-    std::fs::write(&path, content)?;
-    debug!("Saved package cache to {:?}", path);
+    let mut infos: Vec<EntryInfo> = dir
+        .flatten()
+        .filter_map(|e| {
+            let file = e.path();
+            if file.extension().is_none_or(|x| x != "json") {
+                return None;
+            }
+            let meta = e.metadata().ok()?;
+            let cached: PathCache = read_versioned(&file, CACHE_VERSION).ok()??;
 
-    Ok(())
+            let mut fps: Vec<(&String, u64)> = cached
+                .files
+                .iter()
+                .chain(cached.families.iter())
+                .map(|(name, entry)| (name, entry.fingerprint))
+                .collect();
+            fps.sort();
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            fps.hash(&mut hasher);
+
+            Some(EntryInfo {
+                size: meta.len(),
+                modified: meta.modified().ok(),
+                fingerprint: hasher.finish(),
+                families: cached.families.len(),
+                files: cached.files.len(),
+                packages: cached.packages().count(),
+                path: cached.path,
+            })
+        })
+        .collect();
+    infos.sort_by(|a, b| a.path.cmp(&b.path));
+    infos
+}
+
+/// Delete every cache file anvil wrote in `cache_dir`.  Returns how many
+/// entries were removed.  Leaves anything else in the directory alone.
+pub fn clear(cache_dir: &Path) -> Result<usize> {
+    let mut removed = 0;
+
+    for subdir in [SCAN_SUBDIR, RESOLVE_SUBDIR] {
+        let Ok(dir) = std::fs::read_dir(cache_dir.join(subdir)) else {
+            continue;
+        };
+        // Lock files and temp files left by an interrupted write go too,
+        // but only the entries themselves are counted.
+        for entry in dir.flatten() {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            std::fs::remove_file(&path)?;
+            if path.extension().is_some_and(|e| e == "json") {
+                removed += 1;
+            }
+        }
+    }
+
+    let legacy = cache_dir.join(LEGACY_FILE);
+    if legacy.exists() {
+        std::fs::remove_file(&legacy)?;
+        removed += 1;
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_pkg(dir: &Path, name: &str, version: &str) {
+        let version_dir = dir.join(name).join(version);
+        std::fs::create_dir_all(&version_dir).unwrap();
+        std::fs::write(
+            version_dir.join("package.yaml"),
+            format!("name: {}\nversion: \"{}\"\n", name, version),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn rescan_reuses_unchanged_entries() {
+        let root = tempfile::tempdir().unwrap();
+        write_pkg(root.path(), "maya", "2024");
+        write_pkg(root.path(), "python", "3.11");
+        std::fs::write(root.path().join("nuke-15.yaml"), "name: nuke\nversion: \"15\"\n").unwrap();
+
+        let (first, stats) = scan_path(root.path(), None, None).unwrap();
+        assert_eq!(stats.reread, 3);
+        assert_eq!(first.packages().count(), 3);
+
+        let (second, stats) = scan_path(root.path(), Some(first), None).unwrap();
+        assert_eq!(stats, ScanStats::default());
+        assert_eq!(second.packages().count(), 3);
+    }
+
+    #[test]
+    fn new_release_rereads_only_its_family() {
+        let root = tempfile::tempdir().unwrap();
+        write_pkg(root.path(), "maya", "2024");
+        write_pkg(root.path(), "python", "3.11");
+        let (first, _) = scan_path(root.path(), None, None).unwrap();
+
+        write_pkg(root.path(), "maya", "2025");
+        let (second, stats) = scan_path(root.path(), Some(first), None).unwrap();
+        assert_eq!(stats.reread, 1);
+        assert_eq!(second.families["maya"].packages.len(), 2);
+    }
+
+    #[test]
+    fn list_families_without_parsing() {
+        let root = tempfile::tempdir().unwrap();
+        write_pkg(root.path(), "maya", "2024");
+        std::fs::write(root.path().join("studio-tools-1.0.yaml"), "not: [valid").unwrap();
+
+        let families = list_families(&[root.path().to_path_buf()]).unwrap();
+        assert!(families["maya"].iter().all(|s| !s.flat));
+        assert!(families["studio-tools"].iter().all(|s| s.flat));
+
+        let pkgs = load_sources(None, &families["maya"], true);
+        assert_eq!(pkgs.len(), 1);
+        assert_eq!(pkgs[0].version, "2024");
+    }
+
+    #[test]
+    fn entries_and_clear() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        write_pkg(root.path(), "maya", "2024");
+        let paths = vec![root.path().to_path_buf()];
+
+        let pkgs = scan(Some(cache.path()), &paths, false).unwrap();
+        assert_eq!(pkgs.len(), 1);
+        assert!(entry_file(cache.path(), root.path()).exists());
+
+        let infos = entries(cache.path());
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].path, root.path());
+        assert_eq!(infos[0].packages, 1);
+
+        assert_eq!(clear(cache.path()).unwrap(), 1);
+        assert!(entries(cache.path()).is_empty());
+    }
+
+    #[test]
+    fn index_seeds_scan_and_is_rechecked() {
+        let root = tempfile::tempdir().unwrap();
+        write_pkg(root.path(), "maya", "2024");
+        write_pkg(root.path(), "python", "3.11");
+        let summary = write_index(root.path(), true).unwrap();
+        assert_eq!(summary.packages, 2);
+
+        let index = load_index(root.path());
+        let (_, stats) = scan_path(root.path(), None, index).unwrap();
+        assert_eq!(stats.reread, 0);
+        assert_eq!(stats.indexed, 2);
+
+        // A release the index doesn't know about is parsed; the rest isn't.
+        write_pkg(root.path(), "maya", "2025");
+        let index = load_index(root.path());
+        let (scan, stats) = scan_path(root.path(), None, index).unwrap();
+        assert_eq!(stats.reread, 1);
+        assert_eq!(stats.indexed, 1);
+        assert_eq!(scan.families["maya"].packages.len(), 2);
+    }
+
+    #[test]
+    fn index_rebased_on_another_mount() {
+        let tmp = tempfile::tempdir().unwrap();
+        let built = tmp.path().join("built");
+        write_pkg(&built, "maya", "2024");
+        write_index(&built, true).unwrap();
+
+        // Renaming keeps mtimes, like the same share mounted elsewhere.
+        let mounted = tmp.path().join("mounted");
+        std::fs::rename(&built, &mounted).unwrap();
+
+        let index = load_index(&mounted).unwrap();
+        let (scan, stats) = scan_path(&mounted, None, Some(index)).unwrap();
+        assert_eq!(stats.reread, 0);
+        let pkg = scan.packages().next().unwrap();
+        assert!(pkg.root.starts_with(&mounted));
+    }
+
+    #[test]
+    fn index_with_other_version_is_ignored() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join(INDEX_FILE),
+            r#"{"version": 999, "path": "/x", "files": {}, "families": {}}"#,
+        )
+        .unwrap();
+        assert!(load_index(root.path()).is_none());
+    }
+
+    #[test]
+    fn unversioned_or_torn_cache_is_ignored() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        write_pkg(root.path(), "maya", "2024");
+        let file = entry_file(cache.path(), root.path());
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+
+        let unversioned = format!(r#"{{"path": {:?}, "files": {{}}, "families": {{}}}}"#, root.path());
+        std::fs::write(&file, unversioned).unwrap();
+        assert!(load(cache.path(), root.path()).is_none());
+
+        std::fs::write(&file, r#"{"version": 2, "path": "/x", "fami"#).unwrap();
+        assert!(load(cache.path(), root.path()).is_none());
+
+        // A scan over either still works and replaces the file.
+        let pkgs = scan(Some(cache.path()), &[root.path().to_path_buf()], false).unwrap();
+        assert_eq!(pkgs.len(), 1);
+        assert!(load(cache.path(), root.path()).is_some());
+    }
+
+    #[test]
+    fn concurrent_writes_never_tear() {
+        let cache = tempfile::tempdir().unwrap();
+        let file = cache.path().join("entry.json");
+        // Flattened into the versioned file, so it has to be a map.
+        let payload: std::collections::BTreeMap<String, u32> =
+            (0..2000).map(|i| (format!("key-{}", i), i)).collect();
+        write_versioned(&file, CACHE_VERSION, &payload, true).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        write_versioned(&file, CACHE_VERSION, &payload, false).unwrap();
+                    }
+                });
+            }
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        let read: std::collections::BTreeMap<String, u32> =
+                            read_versioned(&file, CACHE_VERSION).unwrap().unwrap();
+                        assert_eq!(read.len(), payload.len());
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn held_lock_skips_write() {
+        let cache = tempfile::tempdir().unwrap();
+        let file = cache.path().join("entry.json");
+        let lock = std::fs::File::create(file.with_extension("lock")).unwrap();
+        lock.lock().unwrap();
+
+        let entry = PathCache::default();
+        assert!(!write_versioned(&file, CACHE_VERSION, &entry, false).unwrap());
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn write_leaves_no_lock_or_temp_files() {
+        let root = tempfile::tempdir().unwrap();
+        let file = root.path().join(INDEX_FILE);
+        let leftover = root.path().join(format!(".{}.abc123.tmp", INDEX_FILE));
+        std::fs::write(&leftover, "{").unwrap();
+
+        assert!(write_versioned(&file, INDEX_VERSION, &PathCache::default(), true).unwrap());
+        let names: Vec<String> = std::fs::read_dir(root.path())
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect();
+        if cfg!(unix) {
+            assert_eq!(names, vec![INDEX_FILE.to_string()]);
+        } else {
+            assert!(!leftover.exists());
+        }
+    }
+
+    #[test]
+    fn removed_family_is_dropped() {
+        let root = tempfile::tempdir().unwrap();
+        write_pkg(root.path(), "maya", "2024");
+        write_pkg(root.path(), "python", "3.11");
+        let (first, _) = scan_path(root.path(), None, None).unwrap();
+
+        std::fs::remove_dir_all(root.path().join("python")).unwrap();
+        let (second, stats) = scan_path(root.path(), Some(first), None).unwrap();
+        assert_eq!(stats.removed, 1);
+        assert!(!second.families.contains_key("python"));
+    }
 }
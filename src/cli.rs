@@ -36,6 +36,21 @@ pub enum Commands {
         /// Output as JSON
         #[arg(short, long)]
         json: bool,
+
+        /// Output in another format: sh, dotenv, dockerfile, systemd,
+        /// github-actions, powershell, fish or csh
+        #[arg(long, value_parser = crate::shell::ENV_FORMATS.to_vec(), conflicts_with_all = ["export", "json"])]
+        format: Option<String>,
+
+        /// Start from a minimal environment (HOME, USER, DISPLAY, TERM plus
+        /// `inherit.allow`) instead of inheriting the caller's
+        #[arg(long)]
+        pure: bool,
+
+        /// Resolve for another platform, as `os` or `os-arch` (e.g.
+        /// `windows`, `linux-aarch64`); defaults to the host
+        #[arg(long)]
+        platform: Option<String>,
     },
 
     /// Run a command with resolved environment
@@ -48,6 +63,11 @@ pub enum Commands {
         #[arg(short, long = "env")]
         env_vars: Vec<String>,
 
+        /// Start from a minimal environment (HOME, USER, DISPLAY, TERM plus
+        /// `inherit.allow`) instead of inheriting the caller's
+        #[arg(long)]
+        pure: bool,
+
         /// Command to run (after --)
         #[arg(last = true, required = true)]
         command: Vec<String>,
@@ -59,7 +79,7 @@ pub enum Commands {
         #[arg(required = true)]
         packages: Vec<String>,
 
-        /// Shell to use (defaults to $SHELL or bash)
+        /// Shell to use (defaults to the calling shell, then $SHELL, then bash)
         #[arg(short, long)]
         shell: Option<String>,
 
@@ -70,12 +90,46 @@ pub enum Commands {
         /// Skip the orphan-shim sweep on entry (debugging aid).
         #[arg(long)]
         no_sweep: bool,
+
+        /// Start from a minimal environment (HOME, USER, DISPLAY, TERM plus
+        /// `inherit.allow`) instead of inheriting the caller's
+        #[arg(long)]
+        pure: bool,
+    },
+
+    /// Print shell code that applies the resolved environment to the
+    /// current shell: `eval "$(anvil activate maya-2024)"`
+    Activate {
+        /// Packages to resolve
+        #[arg(required = true)]
+        packages: Vec<String>,
+
+        /// Shell to write for: bash, sh, zsh, fish, tcsh, nu, elvish, xonsh or pwsh (defaults to the calling shell)
+        #[arg(short, long)]
+        shell: Option<String>,
+
+        /// Start from a minimal environment (HOME, USER, DISPLAY, TERM plus
+        /// `inherit.allow`) instead of inheriting the caller's
+        #[arg(long)]
+        pure: bool,
+    },
+
+    /// Print shell code that undoes `anvil activate`, restoring every
+    /// variable it changed or unset
+    Deactivate {
+        /// Shell to write for: bash, sh, zsh, fish, tcsh, nu, elvish, xonsh or pwsh (defaults to the calling shell)
+        #[arg(short, long)]
+        shell: Option<String>,
     },
 
     /// List available packages
     List {
         /// Package name to list versions of (optional)
         package: Option<String>,
+
+        /// Show package versions hidden by `filters:` and the rule that hid them
+        #[arg(long)]
+        hidden: bool,
     },
 
     /// Show detailed package information
@@ -97,13 +151,32 @@ pub enum Commands {
 
     /// Pin resolved versions to a lockfile for reproducible environments
     Lock {
-        /// Packages to resolve and pin
-        #[arg(required = true)]
+        /// Packages to resolve and pin.  With `--update`, the package names
+        /// to bump (default: all); with `--check`, the expected requests
+        /// (default: the lockfile's own)
+        #[arg(required_unless_present_any = ["check", "update"])]
         packages: Vec<String>,
 
-        /// Re-resolve even if anvil.lock already exists
+        /// Re-resolve the existing lockfile's requests, bumping only the
+        /// named packages and keeping every other pin
         #[arg(long)]
         update: bool,
+
+        /// Verify anvil.lock instead of writing it: exit non-zero when the
+        /// requests, pins or pinned package definitions have drifted
+        #[arg(long, conflicts_with = "update")]
+        check: bool,
+
+        /// Resolve for each of these platforms and store a pin set per
+        /// platform (e.g. `linux,macos,windows`)
+        #[arg(
+            long,
+            visible_alias = "platform",
+            value_delimiter = ',',
+            value_parser = ["linux", "macos", "windows"],
+            conflicts_with_all = ["update", "check"]
+        )]
+        platforms: Vec<String>,
     },
 
     /// Save and restore complete resolved environments
@@ -112,6 +185,30 @@ pub enum Commands {
         action: ContextAction,
     },
 
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Inspect package set aliases
+    Alias {
+        #[command(subcommand)]
+        action: AliasAction,
+    },
+
+    /// Inspect, clear or pre-populate the package scan cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Maintain the prebuilt index of a shared package path
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+
     /// Scaffold a new package definition (or `--config` for the global config)
     Init {
         /// Package name (e.g., my-tools). Omit when using `--config`.
@@ -147,7 +244,7 @@ pub enum Commands {
         #[arg(short, long, default_value = ".")]
         dir: String,
 
-        /// Shell for wrapper scripts (bash, zsh, fish, powershell)
+        /// Shell for wrapper scripts (bash, zsh, fish, tcsh, nu, elvish, xonsh, powershell)
         #[arg(long, default_value = "bash")]
         shell: String,
     },
@@ -190,6 +287,21 @@ pub enum ContextAction {
         /// Output file path
         #[arg(short, long, default_value = "context.json")]
         output: String,
+
+        /// Start from a minimal environment (HOME, USER, DISPLAY, TERM plus
+        /// `inherit.allow`) instead of inheriting the caller's
+        #[arg(long)]
+        pure: bool,
+
+        /// Resolve for another platform, as `os` or `os-arch` (e.g.
+        /// `windows`, `linux-aarch64`); defaults to the host
+        #[arg(long)]
+        platform: Option<String>,
+
+        /// Also store the caller's environment, so `--captured-base` can
+        /// replay the context exactly as resolved here
+        #[arg(long)]
+        capture_base: bool,
     },
 
     /// Display the environment from a saved context
@@ -204,6 +316,51 @@ pub enum ContextAction {
         /// Output as shell export statements
         #[arg(short, long)]
         export: bool,
+
+        /// Output in another format: sh, dotenv, dockerfile, systemd,
+        /// github-actions, powershell, fish or csh
+        #[arg(long, value_parser = crate::shell::ENV_FORMATS.to_vec(), conflicts_with_all = ["export", "json"])]
+        format: Option<String>,
+    },
+
+    /// Check that a saved context is still usable; the exit status has one
+    /// bit per kind of problem found
+    Verify {
+        /// Context file to load
+        file: String,
+
+        /// Also re-resolve the stored requests and report packages that
+        /// would now get other versions
+        #[arg(long)]
+        resolve: bool,
+    },
+
+    /// Write a copy of a saved context with its paths translated for
+    /// another platform, following `path_mappings:`
+    Remap {
+        /// Context file to load
+        file: String,
+
+        /// Platform to translate to
+        #[arg(long, value_parser = ["linux", "macos", "windows"])]
+        to: String,
+
+        /// Output file path (default: `<file>.<platform>.json`)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Compare two saved contexts: packages, then environment variables
+    Diff {
+        /// Context to compare from
+        a: String,
+
+        /// Context to compare to
+        b: String,
+
+        /// Output as JSON
+        #[arg(short, long)]
+        json: bool,
     },
 
     /// Run a command using a saved context's environment
@@ -211,6 +368,15 @@ pub enum ContextAction {
         /// Context file to load
         file: String,
 
+        /// Start from the environment captured at save time instead of
+        /// this machine's
+        #[arg(long)]
+        captured_base: bool,
+
+        /// Additional environment variables (KEY=VALUE)
+        #[arg(short, long = "env")]
+        env_vars: Vec<String>,
+
         /// Command to run (after --)
         #[arg(last = true, required = true)]
         command: Vec<String>,
@@ -221,8 +387,70 @@ pub enum ContextAction {
         /// Context file to load
         file: String,
 
+        /// Start from the environment captured at save time instead of
+        /// this machine's
+        #[arg(long)]
+        captured_base: bool,
+
         /// Shell to use
         #[arg(short, long)]
         shell: Option<String>,
+
+        /// Don't materialise the context's commands as PATH shims.
+        #[arg(long)]
+        env_only: bool,
+
+        /// Skip the orphan-shim sweep on entry (debugging aid).
+        #[arg(long)]
+        no_sweep: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print the merged config, annotating each value with where it came from
+    Show {
+        /// Output as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+
+    /// List every config file consulted, in load order
+    Path,
+}
+
+#[derive(Subcommand)]
+pub enum AliasAction {
+    /// List all aliases and their members
+    List,
+
+    /// Show an alias's members and its full expansion
+    Show {
+        /// Alias name, optionally with a parameter (e.g., studio-maya@2025)
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Show the cache location, size and per-path entries
+    Info,
+
+    /// Delete every cached package scan
+    Clear,
+
+    /// Scan package paths now so later commands start warm
+    Warm {
+        /// Package paths to scan (default: the configured package paths)
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum IndexAction {
+    /// Parse every package in a package path and write its index
+    Build {
+        /// Package path to index (e.g., /studio/packages)
+        path: String,
     },
 }
@@ -1,10 +1,12 @@
 //! Configuration loading and management
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{info, warn};
 
 /// Global configuration for anvil
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -35,6 +37,139 @@ pub struct Config {
     /// `anvil shell` behaviour
     #[serde(default)]
     pub shell: ShellConfig,
+
+    /// Which variables of the caller's environment are inherited
+    #[serde(default)]
+    pub inherit: InheritConfig,
+
+    /// Where the package scan cache lives (default `~/.cache/anvil`).
+    /// `ANVIL_CACHE_DIR` overrides it.
+    pub cache_dir: Option<String>,
+
+    /// Fail when a configured package path is unreachable instead of
+    /// falling back to its last cached scan.
+    pub require_package_paths: Option<bool>,
+
+    /// Per-package patches keyed by package request (e.g. `maya-2024`),
+    /// applied after the package definition loads.
+    #[serde(default)]
+    pub overrides: IndexMap<String, PackageOverride>,
+
+    /// The same locations as each platform mounts them, used to translate
+    /// paths when resolving for, or loading a context on, another platform.
+    #[serde(default)]
+    pub path_mappings: Vec<PathMapping>,
+
+    /// Where each effective value came from (filled in by `load`).
+    #[serde(skip)]
+    pub provenance: Provenance,
+}
+
+/// The layer of the config stack a value was contributed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    /// Built-in default (no file involved).
+    Default,
+    /// Read from an environment variable such as `ANVIL_PACKAGES`.
+    Env,
+    /// The global config (`ANVIL_CONFIG`, `~/.anvil.yaml`, ...).
+    Global,
+    /// A project-local `.anvil.yaml`.
+    Project,
+    /// A `platform:` override for the current OS.
+    Platform,
+}
+
+impl std::fmt::Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Layer::Default => "default",
+            Layer::Env => "env",
+            Layer::Global => "global",
+            Layer::Project => "project",
+            Layer::Platform => "platform",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Origin of a single config value: its layer and, when it came from a
+/// file, that file's path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Origin {
+    pub layer: Layer,
+    pub file: Option<PathBuf>,
+}
+
+impl Origin {
+    fn new(layer: Layer, file: Option<&Path>) -> Self {
+        Origin {
+            layer,
+            file: file.map(|p| p.to_path_buf()),
+        }
+    }
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{} ({})", self.layer, file.display()),
+            None => write!(f, "{}", self.layer),
+        }
+    }
+}
+
+/// A config file looked at by `Config::load`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsultedFile {
+    pub path: PathBuf,
+    pub layer: Layer,
+    /// False when the file was looked for but didn't exist.
+    pub loaded: bool,
+}
+
+/// Per-key provenance for the effective config.
+///
+/// List-valued settings (`package_paths`, hooks) keep one origin per entry,
+/// parallel to the values.  Scalars and map entries (`aliases.<name>`) keep a
+/// single origin; a missing entry means the built-in default is in effect.
+#[derive(Debug, Clone, Default)]
+pub struct Provenance {
+    /// Every config file consulted, in load order.
+    pub files: Vec<ConsultedFile>,
+    lists: HashMap<String, Vec<Origin>>,
+    values: HashMap<String, Origin>,
+}
+
+impl Provenance {
+    /// Origins for each entry of a list-valued setting.
+    pub fn list(&self, key: &str) -> &[Origin] {
+        self.lists.get(key).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// Origin of a scalar setting or map entry, if it isn't a default.
+    pub fn value(&self, key: &str) -> Option<&Origin> {
+        self.values.get(key)
+    }
+
+    fn set_list(&mut self, key: &str, len: usize, origin: &Origin) {
+        self.lists.insert(key.to_string(), vec![origin.clone(); len]);
+    }
+
+    /// Put `front`'s origins for `key` ahead of ours, mirroring how the
+    /// values themselves are merged.
+    fn prepend_list(&mut self, key: &str, front: &mut Provenance) {
+        let mut merged = front.lists.remove(key).unwrap_or_default();
+        merged.extend(self.lists.remove(key).unwrap_or_default());
+        self.lists.insert(key.to_string(), merged);
+    }
+
+    fn take_value(&mut self, key: &str, other: &mut Provenance) {
+        if let Some(origin) = other.values.remove(key) {
+            self.values.insert(key.to_string(), origin);
+        }
+    }
 }
 
 /// Controls how `anvil shell` composes the interactive subshell.
@@ -80,6 +215,132 @@ pub struct PlatformOverrides {
     pub package_paths: Option<Vec<String>>,
 }
 
+/// How long a package path may take to answer a `stat` before it counts
+/// as unreachable.
+const REACHABILITY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Paths that already timed out in this process, so each hung mount costs
+/// one timeout rather than one per lookup.
+static HUNG_PATHS: std::sync::Mutex<Vec<PathBuf>> = std::sync::Mutex::new(Vec::new());
+
+/// Whether `path` exists and answers within [`REACHABILITY_TIMEOUT`].  A
+/// hard NFS mount whose server is gone blocks `stat` indefinitely, so the
+/// check runs on its own thread; on timeout that thread is left behind.
+fn reachable(path: &Path) -> bool {
+    let mut hung = HUNG_PATHS.lock().unwrap_or_else(|e| e.into_inner());
+    if hung.iter().any(|p| p == path) {
+        return false;
+    }
+    let (tx, rx) = std::sync::mpsc::channel();
+    let probe = path.to_path_buf();
+    std::thread::spawn(move || {
+        let _ = tx.send(probe.exists());
+    });
+    match rx.recv_timeout(REACHABILITY_TIMEOUT) {
+        Ok(exists) => exists,
+        Err(_) => {
+            warn!(
+                "Package path {} did not answer within {}s",
+                path.display(),
+                REACHABILITY_TIMEOUT.as_secs()
+            );
+            hung.push(path.to_path_buf());
+            false
+        }
+    }
+}
+
+/// Variables kept in pure mode regardless of `inherit.allow`.
+#[cfg(not(target_os = "windows"))]
+const PURE_BASE: &[&str] = &["HOME", "USER", "DISPLAY", "TERM"];
+/// Variables kept in pure mode regardless of `inherit.allow`.  Windows
+/// processes also need `SYSTEMROOT` and friends to start at all.
+#[cfg(target_os = "windows")]
+const PURE_BASE: &[&str] = &[
+    "HOME", "USER", "DISPLAY", "TERM", "SYSTEMROOT", "USERPROFILE", "USERNAME", "COMSPEC", "PATHEXT",
+];
+
+/// Controls which variables of the caller's environment are inherited by
+/// resolved environments.  Patterns use glob syntax (`*`, `?`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct InheritConfig {
+    /// Start from a minimal base (HOME, USER, DISPLAY, TERM plus `allow`)
+    /// instead of the full caller environment.  `--pure` turns this on.
+    #[serde(default)]
+    pub pure: bool,
+    /// Extra variables kept in pure mode.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Variables never inherited, pure or not.  Wins over `allow`.
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl InheritConfig {
+    /// Return true if the caller's `key` should be inherited.
+    pub fn inherits(&self, key: &str) -> bool {
+        if self.deny.iter().any(|pat| glob_match(pat, key)) {
+            return false;
+        }
+        !self.pure
+            || PURE_BASE.contains(&key)
+            || self.allow.iter().any(|pat| glob_match(pat, key))
+    }
+
+    /// The caller's environment filtered by this policy: the base that
+    /// package environments are layered on top of.
+    pub fn base_environment(&self) -> HashMap<String, String> {
+        std::env::vars().filter(|(k, _)| self.inherits(k)).collect()
+    }
+}
+
+/// One location as each platform mounts it, e.g. `/mnt/studio` on Linux,
+/// `/Volumes/Studio` on macOS and `S:/` on Windows.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct PathMapping {
+    pub linux: Option<String>,
+    pub macos: Option<String>,
+    pub windows: Option<String>,
+}
+
+impl PathMapping {
+    /// The prefix for `os` (linux, macos, windows), if the mapping has one.
+    pub fn prefix(&self, os: &str) -> Option<&str> {
+        match os {
+            "linux" => self.linux.as_deref(),
+            "macos" => self.macos.as_deref(),
+            "windows" => self.windows.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PathMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = ["linux", "macos", "windows"]
+            .iter()
+            .filter_map(|os| self.prefix(os).map(|p| format!("{}={}", os, p)))
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// A config-level patch to a package definition.  Lets a show add or
+/// replace environment entries, requires, or commands without editing the
+/// shared package repository.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PackageOverride {
+    /// Environment entries to add, or replace when the package already sets them.
+    #[serde(default)]
+    pub environment: IndexMap<String, String>,
+    /// Extra requirements appended to the package's own.
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Command aliases to add or replace.
+    #[serde(default)]
+    pub commands: HashMap<String, String>,
+}
+
 /// Lifecycle hooks: shell commands run at specific points.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct HooksConfig {
@@ -98,33 +359,70 @@ pub struct HooksConfig {
 }
 
 /// Package include/exclude filters.  When `include` is non-empty, only
-/// matching packages are visible.  `exclude` patterns are applied after
-/// include.  Patterns use glob syntax (`*`, `?`).
+/// matching package versions are visible.  `exclude` patterns are applied
+/// after include.  A pattern is a glob (`*`, `?`) matched against the name
+/// or the `name-version` id, or a request such as `maya-<2024`.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FiltersConfig {
-    /// Only allow packages whose names match at least one pattern.
+    /// Only allow packages matching at least one pattern.
     #[serde(default)]
     pub include: Vec<String>,
-    /// Hide packages whose names match any pattern.
+    /// Hide packages matching any pattern.
     #[serde(default)]
     pub exclude: Vec<String>,
 }
 
 impl FiltersConfig {
-    /// Return true if the package name passes the include/exclude filters.
-    pub fn allows(&self, name: &str) -> bool {
-        // If include list is non-empty, name must match at least one pattern.
-        if !self.include.is_empty() {
-            let included = self.include.iter().any(|pat| glob_match(pat, name));
-            if !included {
-                return false;
-            }
+    /// Return true if the package version passes the include/exclude filters.
+    #[cfg(test)]
+    pub fn allows(&self, name: &str, version: &str) -> bool {
+        self.hidden_by(name, version).is_none()
+    }
+
+    /// Return a description of the rule that hides this package version,
+    /// or `None` if it is visible.
+    pub fn hidden_by(&self, name: &str, version: &str) -> Option<String> {
+        // If include list is non-empty, the package must match at least one pattern.
+        if !self.include.is_empty()
+            && !self.include.iter().any(|pat| filter_match(pat, name, version))
+        {
+            return Some("not matched by any include pattern".to_string());
         }
         // Exclude overrides include.
-        !self.exclude.iter().any(|pat| glob_match(pat, name))
+        self.exclude
+            .iter()
+            .find(|pat| filter_match(pat, name, version))
+            .map(|pat| format!("exclude {:?}", pat))
+    }
+}
+
+/// Match a filter pattern against one package version.
+fn filter_match(pattern: &str, name: &str, version: &str) -> bool {
+    if glob_match(pattern, name) || glob_match(pattern, &format!("{}-{}", name, version)) {
+        return true;
+    }
+    match crate::package::PackageRequest::parse(pattern) {
+        Ok(req) if !matches!(req.version_constraint, crate::package::VersionConstraint::Any) => {
+            glob_match(&req.name, name) && req.matches(version)
+        }
+        _ => false,
+    }
+}
+
+/// Split an alias invocation `name@param` into its parts.
+fn split_alias_request(request: &str) -> (&str, Option<&str>) {
+    match request.split_once('@') {
+        Some((name, param)) => (name, Some(param)),
+        None => (request, None),
     }
 }
 
+/// Placeholder in alias members that receives the `@param` of an
+/// invocation.  `${ARG:-2024}` supplies a default when none is given.
+fn alias_param_re() -> regex::Regex {
+    regex::Regex::new(r"\$\{ARG(?::-([^}]*))?\}").unwrap()
+}
+
 /// Simple glob matching supporting `*` (any chars) and `?` (single char).
 fn glob_match(pattern: &str, text: &str) -> bool {
     let p: Vec<char> = pattern.chars().collect();
@@ -163,11 +461,25 @@ impl Config {
         let mut config = if global_path.exists() {
             let content = std::fs::read_to_string(&global_path)
                 .with_context(|| format!("Failed to read config: {:?}", global_path))?;
-            serde_yaml::from_str(&content)
-                .with_context(|| format!("Failed to parse config: {:?}", global_path))?
+            let mut c: Config = serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse config: {:?}", global_path))?;
+            c.stamp(Layer::Global, &global_path);
+            c
         } else {
             let mut c = Config::default();
-            c.package_paths = Self::default_package_paths();
+            c.provenance.files.push(ConsultedFile {
+                path: global_path.clone(),
+                layer: Layer::Global,
+                loaded: false,
+            });
+            for (path, origin) in Self::default_package_paths() {
+                c.package_paths.push(path);
+                c.provenance
+                    .lists
+                    .entry("package_paths".to_string())
+                    .or_default()
+                    .push(origin);
+            }
             c
         };
 
@@ -177,9 +489,17 @@ impl Config {
             info!("Loading project config: {:?}", project_path);
             let content = std::fs::read_to_string(&project_path)
                 .with_context(|| format!("Failed to read project config: {:?}", project_path))?;
-            let project: Config = serde_yaml::from_str(&content)
+            let mut project: Config = serde_yaml::from_str(&content)
                 .with_context(|| format!("Failed to parse project config: {:?}", project_path))?;
+            project.stamp(Layer::Project, &project_path);
             config.merge(project);
+        } else if let Ok(cwd) = std::env::current_dir() {
+            // Nothing found walking up; report where the search started.
+            config.provenance.files.push(ConsultedFile {
+                path: cwd.join(".anvil.yaml"),
+                layer: Layer::Project,
+                loaded: false,
+            });
         }
 
         // Apply platform overrides and expand paths after merging
@@ -247,45 +567,115 @@ impl Config {
             .join(".anvil.yaml")
     }
 
-    /// Default package search paths
-    fn default_package_paths() -> Vec<String> {
+    /// Default package search paths, each with where it came from.
+    fn default_package_paths() -> Vec<(String, Origin)> {
         let mut paths = Vec::new();
+        let env = Origin::new(Layer::Env, None);
+        let default = Origin::new(Layer::Default, None);
 
         if let Ok(pkg_path) = std::env::var("ANVIL_PACKAGES") {
             for p in pkg_path.split(':') {
-                paths.push(p.to_string());
+                paths.push((p.to_string(), env.clone()));
             }
         }
 
         if let Some(home) = dirs::home_dir() {
-            paths.push(home.join("packages").to_string_lossy().to_string());
-            paths.push(
+            paths.push((home.join("packages").to_string_lossy().to_string(), default.clone()));
+            paths.push((
                 home.join(".local/share/anvil/packages")
                     .to_string_lossy()
                     .to_string(),
-            );
+                default.clone(),
+            ));
         }
 
-        paths.push("/opt/packages".to_string());
+        paths.push(("/opt/packages".to_string(), default));
 
         paths
     }
 
+    /// Record `file` as the origin of every value set in this freshly
+    /// parsed config.  Values left at their defaults get no origin.
+    fn stamp(&mut self, layer: Layer, file: &Path) {
+        let origin = Origin::new(layer, Some(file));
+        let mut prov = Provenance {
+            files: vec![ConsultedFile {
+                path: file.to_path_buf(),
+                layer,
+                loaded: true,
+            }],
+            ..Provenance::default()
+        };
+
+        prov.set_list("package_paths", self.package_paths.len(), &origin);
+        prov.set_list("hooks.pre_resolve", self.hooks.pre_resolve.len(), &origin);
+        prov.set_list("hooks.post_resolve", self.hooks.post_resolve.len(), &origin);
+        prov.set_list("hooks.pre_run", self.hooks.pre_run.len(), &origin);
+        prov.set_list("hooks.post_run", self.hooks.post_run.len(), &origin);
+
+        let platforms = [
+            ("linux", &self.platform.linux),
+            ("macos", &self.platform.macos),
+            ("windows", &self.platform.windows),
+        ];
+        for (os, overrides) in platforms {
+            if let Some(paths) = overrides.as_ref().and_then(|o| o.package_paths.as_ref()) {
+                let platform_origin = Origin::new(Layer::Platform, Some(file));
+                prov.set_list(&format!("platform.{}.package_paths", os), paths.len(), &platform_origin);
+            }
+        }
+
+        for name in self.aliases.keys() {
+            prov.values.insert(format!("aliases.{}", name), origin.clone());
+        }
+        if self.default_shell.is_some() {
+            prov.values.insert("default_shell".to_string(), origin.clone());
+        }
+        if !self.filters.include.is_empty() || !self.filters.exclude.is_empty() {
+            prov.values.insert("filters".to_string(), origin.clone());
+        }
+        if self.shell != ShellConfig::default() {
+            prov.values.insert("shell".to_string(), origin.clone());
+        }
+        if self.inherit != InheritConfig::default() {
+            prov.values.insert("inherit".to_string(), origin.clone());
+        }
+        if self.cache_dir.is_some() {
+            prov.values.insert("cache_dir".to_string(), origin.clone());
+        }
+        if self.require_package_paths.is_some() {
+            prov.values.insert("require_package_paths".to_string(), origin.clone());
+        }
+        for key in self.overrides.keys() {
+            prov.values.insert(format!("overrides.{}", key), origin.clone());
+        }
+        prov.set_list("path_mappings", self.path_mappings.len(), &origin);
+
+        self.provenance = prov;
+    }
+
     /// Apply platform-specific overrides
     fn apply_platform_overrides(&mut self) {
-        let overrides = if cfg!(target_os = "linux") {
-            self.platform.linux.as_ref()
+        let (os, overrides) = if cfg!(target_os = "linux") {
+            ("linux", self.platform.linux.as_ref())
         } else if cfg!(target_os = "windows") {
-            self.platform.windows.as_ref()
+            ("windows", self.platform.windows.as_ref())
         } else if cfg!(target_os = "macos") {
-            self.platform.macos.as_ref()
+            ("macos", self.platform.macos.as_ref())
         } else {
-            None
+            return;
         };
 
         if let Some(overrides) = overrides {
             if let Some(paths) = &overrides.package_paths {
                 self.package_paths.extend(paths.clone());
+                let key = format!("platform.{}.package_paths", os);
+                let origins = self.provenance.lists.remove(&key).unwrap_or_default();
+                self.provenance
+                    .lists
+                    .entry("package_paths".to_string())
+                    .or_default()
+                    .extend(origins);
             }
         }
     }
@@ -301,6 +691,74 @@ impl Config {
                     .unwrap_or_else(|_| p.clone())
             })
             .collect();
+        if let Some(dir) = &self.cache_dir {
+            self.cache_dir = Some(
+                shellexpand::full(dir)
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|_| dir.clone()),
+            );
+        }
+    }
+
+    /// Directory for the package scan cache: `ANVIL_CACHE_DIR`, then the
+    /// `cache_dir` setting, then the platform cache dir.  `None` disables
+    /// caching.
+    pub fn cache_root(&self) -> Option<PathBuf> {
+        if let Ok(dir) = std::env::var("ANVIL_CACHE_DIR") {
+            if !dir.is_empty() {
+                return Some(PathBuf::from(shellexpand::tilde(&dir).into_owned()));
+            }
+        }
+        match &self.cache_dir {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None => crate::cache::default_dir(),
+        }
+    }
+
+    /// Translate a path from how platform `from` mounts it to how `to`
+    /// does, using the first matching `path_mappings` entry.
+    pub fn remap_path(&self, path: &str, from: &str, to: &str) -> Option<String> {
+        for mapping in &self.path_mappings {
+            let (Some(src), Some(dst)) = (mapping.prefix(from), mapping.prefix(to)) else {
+                continue;
+            };
+            let src = src.trim_end_matches(['/', '\\']);
+            let Some(rest) = path.strip_prefix(src) else {
+                continue;
+            };
+            if rest.is_empty() {
+                return Some(dst.to_string());
+            }
+            if rest.starts_with(['/', '\\']) {
+                return Some(format!("{}{}", dst.trim_end_matches(['/', '\\']), rest));
+            }
+        }
+        None
+    }
+
+    /// Translate the paths in an environment value from platform `from` to
+    /// platform `to`.  Path lists are translated segment by segment and
+    /// re-joined with the target's separator.
+    pub fn remap_value(&self, value: &str, from: &str, to: &str) -> String {
+        if from == to || self.path_mappings.is_empty() {
+            return value.to_string();
+        }
+
+        let sep = crate::package::pathsep_for(from);
+        let is_list = value.contains(sep)
+            && value
+                .split(sep)
+                .filter(|s| !s.is_empty())
+                .all(|s| s.starts_with("${") || crate::package::looks_like_path(s));
+        if !is_list {
+            return self.remap_path(value, from, to).unwrap_or_else(|| value.to_string());
+        }
+
+        value
+            .split(sep)
+            .map(|s| self.remap_path(s, from, to).unwrap_or_else(|| s.to_string()))
+            .collect::<Vec<_>>()
+            .join(crate::package::pathsep_for(to))
     }
 
     /// Get all package paths (with deduplication)
@@ -310,7 +768,7 @@ impl Config {
             .iter()
             .filter_map(|p| {
                 let path = PathBuf::from(p);
-                if seen.insert(path.clone()) && path.exists() {
+                if seen.insert(path.clone()) && reachable(&path) {
                     Some(path)
                 } else {
                     None
@@ -319,9 +777,85 @@ impl Config {
             .collect()
     }
 
-    /// Resolve an alias to package list
-    pub fn resolve_alias(&self, name: &str) -> Option<Vec<String>> {
-        self.aliases.get(name).cloned()
+    /// Package paths set by a config file or `ANVIL_PACKAGES` that don't
+    /// exist right now (an unmounted share, say, or a hung one; see
+    /// [`reachable`]).  Built-in default paths are expected to be missing
+    /// and aren't listed.
+    pub fn missing_package_paths(&self) -> Vec<PathBuf> {
+        let origins = self.provenance.list("package_paths");
+        let mut seen = std::collections::HashSet::new();
+        self.package_paths
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| origins.get(*i).is_some_and(|o| o.layer != Layer::Default))
+            .map(|(_, p)| PathBuf::from(p))
+            .filter(|path| seen.insert(path.clone()) && !reachable(path))
+            .collect()
+    }
+
+    /// Expand an alias request (`name` or `name@param`) into package
+    /// requests, following aliases that reference other aliases.  Returns
+    /// `Ok(None)` when `request` doesn't name an alias.
+    pub fn resolve_alias(&self, request: &str) -> Result<Option<Vec<String>>> {
+        let (name, _) = split_alias_request(request);
+        if !self.aliases.contains_key(name) {
+            if request.contains('@') {
+                anyhow::bail!("Unknown alias: {}", name);
+            }
+            return Ok(None);
+        }
+
+        let mut expanded = Vec::new();
+        self.expand_alias_into(request, &mut Vec::new(), &mut expanded)?;
+        Ok(Some(expanded))
+    }
+
+    fn expand_alias_into(
+        &self,
+        request: &str,
+        stack: &mut Vec<String>,
+        out: &mut Vec<String>,
+    ) -> Result<()> {
+        let (name, param) = split_alias_request(request);
+        let Some(members) = self.aliases.get(name) else {
+            if request.contains('@') {
+                anyhow::bail!("Unknown alias: {} (referenced by alias {})", name, stack.join(" -> "));
+            }
+            out.push(request.to_string());
+            return Ok(());
+        };
+
+        if stack.iter().any(|s| s == name) {
+            anyhow::bail!("Alias cycle: {} -> {}", stack.join(" -> "), name);
+        }
+
+        let re = alias_param_re();
+        let takes_param = members.iter().any(|m| re.is_match(m));
+        if param.is_some() && !takes_param {
+            anyhow::bail!("Alias {} takes no parameter (no ${{ARG}} in its members)", name);
+        }
+
+        stack.push(name.to_string());
+        for member in members {
+            let mut missing = false;
+            let substituted = re.replace_all(member, |caps: &regex::Captures| {
+                match (param, caps.get(1)) {
+                    (Some(p), _) => p.to_string(),
+                    (None, Some(default)) => default.as_str().to_string(),
+                    (None, None) => {
+                        missing = true;
+                        String::new()
+                    }
+                }
+            });
+            if missing {
+                anyhow::bail!("Alias {} needs a parameter: use {}@<value>", name, name);
+            }
+            self.expand_alias_into(&substituted, stack, out)?;
+        }
+        stack.pop();
+
+        Ok(())
     }
 
     /// Walk from the current directory upward looking for `.anvil.yaml`.
@@ -344,8 +878,8 @@ impl Config {
         loop {
             let candidate = dir.join(".anvil.yaml");
             if candidate.exists() {
-                if let Some(canonical) = candidate.canonicalize().ok() {
-                    if skip.iter().any(|s| *s == canonical) {
+                if let Ok(canonical) = candidate.canonicalize() {
+                    if skip.contains(&canonical) {
                         return None;
                     }
                 }
@@ -358,49 +892,95 @@ impl Config {
     }
 
     /// Merge another config into this one.
-    fn merge(&mut self, project: Config) {
+    fn merge(&mut self, mut project: Config) {
+        let prov = &mut project.provenance;
+        self.provenance.files.append(&mut prov.files);
+
         // Project paths come first (higher priority)
         let mut merged = project.package_paths;
         merged.append(&mut self.package_paths);
         self.package_paths = merged;
+        self.provenance.prepend_list("package_paths", prov);
 
         // Project aliases override global ones with the same name
+        for name in project.aliases.keys() {
+            self.provenance.take_value(&format!("aliases.{}", name), prov);
+        }
         self.aliases.extend(project.aliases);
 
         // Project shell overrides global
         if project.default_shell.is_some() {
             self.default_shell = project.default_shell;
+            self.provenance.take_value("default_shell", prov);
         }
 
         // Hooks: project hooks are prepended
         let mut pre_resolve = project.hooks.pre_resolve;
-        pre_resolve.extend(self.hooks.pre_resolve.drain(..));
+        pre_resolve.append(&mut self.hooks.pre_resolve);
         self.hooks.pre_resolve = pre_resolve;
+        self.provenance.prepend_list("hooks.pre_resolve", prov);
 
         let mut post_resolve = project.hooks.post_resolve;
-        post_resolve.extend(self.hooks.post_resolve.drain(..));
+        post_resolve.append(&mut self.hooks.post_resolve);
         self.hooks.post_resolve = post_resolve;
+        self.provenance.prepend_list("hooks.post_resolve", prov);
 
         let mut pre_run = project.hooks.pre_run;
-        pre_run.extend(self.hooks.pre_run.drain(..));
+        pre_run.append(&mut self.hooks.pre_run);
         self.hooks.pre_run = pre_run;
+        self.provenance.prepend_list("hooks.pre_run", prov);
 
         let mut post_run = project.hooks.post_run;
-        post_run.extend(self.hooks.post_run.drain(..));
+        post_run.append(&mut self.hooks.post_run);
         self.hooks.post_run = post_run;
+        self.provenance.prepend_list("hooks.post_run", prov);
 
         // Filters: project filters replace global (not merged)
         if !project.filters.include.is_empty() || !project.filters.exclude.is_empty() {
             self.filters = project.filters;
+            self.provenance.take_value("filters", prov);
         }
 
         // Shell: project shell config replaces global only if it differs from
         // the default (serde fills in the default when the project omits `shell:`).
         if project.shell != ShellConfig::default() {
             self.shell = project.shell;
+            self.provenance.take_value("shell", prov);
+        }
+
+        // Project cache dir overrides global
+        if project.cache_dir.is_some() {
+            self.cache_dir = project.cache_dir;
+            self.provenance.take_value("cache_dir", prov);
+        }
+
+        if project.require_package_paths.is_some() {
+            self.require_package_paths = project.require_package_paths;
+            self.provenance.take_value("require_package_paths", prov);
+        }
+
+        // Inherit policy: project replaces global when it sets one
+        if project.inherit != InheritConfig::default() {
+            self.inherit = project.inherit;
+            self.provenance.take_value("inherit", prov);
         }
 
+        // Overrides: project entries replace global ones for the same request
+        for (key, ovr) in project.overrides {
+            self.provenance.take_value(&format!("overrides.{}", key), prov);
+            self.overrides.insert(key, ovr);
+        }
+
+        // Path mappings: project entries come first and win
+        let mut path_mappings = project.path_mappings;
+        path_mappings.append(&mut self.path_mappings);
+        self.path_mappings = path_mappings;
+        self.provenance.prepend_list("path_mappings", prov);
+
         // Merge per-platform paths (project first)
+        for os in ["linux", "macos", "windows"] {
+            self.provenance.prepend_list(&format!("platform.{}.package_paths", os), prov);
+        }
         Self::merge_platform(&mut self.platform.linux, project.platform.linux);
         Self::merge_platform(&mut self.platform.macos, project.platform.macos);
         Self::merge_platform(&mut self.platform.windows, project.platform.windows);
@@ -479,9 +1059,9 @@ mod tests {
             include: vec!["maya-*".into(), "arnold-*".into()],
             exclude: vec![],
         };
-        assert!(f.allows("maya-2024"));
-        assert!(f.allows("arnold-7.2"));
-        assert!(!f.allows("nuke-15"));
+        assert!(f.allows("maya", "2024"));
+        assert!(f.allows("arnold", "7.2"));
+        assert!(!f.allows("nuke", "15"));
     }
 
     #[test]
@@ -490,8 +1070,8 @@ mod tests {
             include: vec![],
             exclude: vec!["*-dev".into()],
         };
-        assert!(f.allows("maya-2024"));
-        assert!(!f.allows("maya-dev"));
+        assert!(f.allows("maya", "2024"));
+        assert!(!f.allows("maya", "dev"));
     }
 
     #[test]
@@ -500,8 +1080,163 @@ mod tests {
             include: vec!["maya-*".into()],
             exclude: vec!["*-dev".into()],
         };
-        assert!(f.allows("maya-2024"));
-        assert!(!f.allows("maya-dev"));
-        assert!(!f.allows("nuke-15"));
+        assert!(f.allows("maya", "2024"));
+        assert!(!f.allows("maya", "dev"));
+        assert!(!f.allows("nuke", "15"));
+    }
+
+    #[test]
+    fn merge_tracks_provenance() {
+        let mut global: Config = serde_yaml::from_str(
+            "package_paths: [/g]\naliases:\n  a: [x]\n  b: [y]\nhooks:\n  pre_run: [g]\n",
+        )
+        .unwrap();
+        global.stamp(Layer::Global, Path::new("/global.yaml"));
+        let mut project: Config = serde_yaml::from_str(
+            "package_paths: [/p]\naliases:\n  b: [z]\nhooks:\n  pre_run: [p]\n",
+        )
+        .unwrap();
+        project.stamp(Layer::Project, Path::new("/project.yaml"));
+        global.merge(project);
+
+        let layers = |key: &str| -> Vec<Layer> {
+            global.provenance.list(key).iter().map(|o| o.layer).collect()
+        };
+        assert_eq!(global.package_paths, vec!["/p", "/g"]);
+        assert_eq!(layers("package_paths"), vec![Layer::Project, Layer::Global]);
+        assert_eq!(layers("hooks.pre_run"), vec![Layer::Project, Layer::Global]);
+        assert_eq!(global.provenance.value("aliases.a").unwrap().layer, Layer::Global);
+        assert_eq!(global.provenance.value("aliases.b").unwrap().layer, Layer::Project);
+        assert!(global.provenance.value("default_shell").is_none());
+        assert_eq!(global.provenance.files.len(), 2);
+    }
+
+    #[test]
+    fn filter_version_constraint() {
+        let f = FiltersConfig {
+            include: vec![],
+            exclude: vec!["maya-<2024".into(), "*-*beta*".into()],
+        };
+        assert!(!f.allows("maya", "2023"));
+        assert!(f.allows("maya", "2024"));
+        assert!(!f.allows("houdini", "20.5beta1"));
+        assert!(f.allows("houdini", "20.5"));
+        assert_eq!(
+            f.hidden_by("maya", "2023").as_deref(),
+            Some("exclude \"maya-<2024\"")
+        );
+    }
+
+    #[test]
+    fn inherit_policy() {
+        let mut policy = InheritConfig {
+            pure: false,
+            allow: vec!["SSH_*".into()],
+            deny: vec!["PYTHONPATH".into(), "LD_*".into()],
+        };
+        assert!(policy.inherits("RANDOM_VAR"));
+        assert!(!policy.inherits("PYTHONPATH"));
+        assert!(!policy.inherits("LD_LIBRARY_PATH"));
+
+        policy.pure = true;
+        assert!(policy.inherits("HOME"));
+        assert!(policy.inherits("SSH_AUTH_SOCK"));
+        assert!(!policy.inherits("RANDOM_VAR"));
+        assert!(!policy.inherits("PYTHONPATH"));
+    }
+
+    fn alias_config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn alias_nested() {
+        let c = alias_config("aliases:\n  base: [python-3.11, usd]\n  maya-set: [maya-2024, base]\n");
+        assert_eq!(
+            c.resolve_alias("maya-set").unwrap().unwrap(),
+            vec!["maya-2024", "python-3.11", "usd"]
+        );
+        assert!(c.resolve_alias("maya-2024").unwrap().is_none());
+    }
+
+    #[test]
+    fn alias_cycle_detected() {
+        let c = alias_config("aliases:\n  a: [b]\n  b: [c]\n  c: [a]\n");
+        let err = c.resolve_alias("a").unwrap_err().to_string();
+        assert!(err.contains("a -> b -> c -> a"), "got: {}", err);
+    }
+
+    #[test]
+    fn alias_parameter_substitution() {
+        let c = alias_config(
+            "aliases:\n  studio-maya: [\"maya-${ARG:-2024}\", studio-tools]\n  host: [\"studio-maya@${ARG}\"]\n",
+        );
+        assert_eq!(
+            c.resolve_alias("studio-maya@2025").unwrap().unwrap(),
+            vec!["maya-2025", "studio-tools"]
+        );
+        assert_eq!(
+            c.resolve_alias("studio-maya").unwrap().unwrap(),
+            vec!["maya-2024", "studio-tools"]
+        );
+        assert_eq!(
+            c.resolve_alias("host@2023").unwrap().unwrap(),
+            vec!["maya-2023", "studio-tools"]
+        );
+        assert!(c.resolve_alias("host").is_err());
+    }
+
+    #[test]
+    fn alias_parameter_rejected_when_unused() {
+        let c = alias_config("aliases:\n  plain: [maya-2024]\n");
+        assert!(c.resolve_alias("plain@2025").is_err());
+        assert!(c.resolve_alias("nope@1").is_err());
+    }
+
+    #[test]
+    fn reachable_paths() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(reachable(dir.path()));
+        assert!(!reachable(&dir.path().join("unmounted")));
+
+        // A path that timed out once isn't probed again.
+        HUNG_PATHS.lock().unwrap().push(dir.path().to_path_buf());
+        assert!(!reachable(dir.path()));
+    }
+
+    #[test]
+    fn remap_values_between_platforms() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+path_mappings:
+  - linux: /mnt/studio
+    windows: "S:/"
+    macos: /Volumes/Studio
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.remap_value("/mnt/studio/maya/bin", "linux", "windows"),
+            "S:/maya/bin"
+        );
+        assert_eq!(config.remap_value("/mnt/studio", "linux", "windows"), "S:/");
+        assert_eq!(
+            config.remap_value("/mnt/studio/a:/usr/bin:${PATH}", "linux", "windows"),
+            "S:/a;/usr/bin;${PATH}"
+        );
+        assert_eq!(
+            config.remap_value("S:/a;S:/b", "windows", "macos"),
+            "/Volumes/Studio/a:/Volumes/Studio/b"
+        );
+        // Not a prefix at a path boundary, and not a path.
+        assert_eq!(
+            config.remap_value("/mnt/studiox/a", "linux", "windows"),
+            "/mnt/studiox/a"
+        );
+        assert_eq!(
+            config.remap_value("http://mnt/studio", "linux", "windows"),
+            "http://mnt/studio"
+        );
     }
 }
@@ -1,11 +1,14 @@
 //! Lockfiles and saved contexts for reproducible environments.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context as _, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::config::InheritConfig;
+use crate::package::Package;
+
 // ---------------------------------------------------------------------------
 // Lockfile
 // ---------------------------------------------------------------------------
@@ -15,15 +18,98 @@ use serde::{Deserialize, Serialize};
 /// Stored as `anvil.lock` in the project directory.  When present, the
 /// resolver prefers pinned versions over the default "highest matching"
 /// strategy.
+///
+/// A lockfile holds either one pin set (top-level `pins`, used on every
+/// platform) or, from `anvil lock --platforms`, one set per platform.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Lockfile {
     /// Original package requests that produced this lockfile.
     pub requests: Vec<String>,
+    /// The single pin set.  Empty when `platforms` is used.
+    #[serde(flatten)]
+    pub locked: PinSet,
+    /// Per-platform pin sets, keyed by linux / macos / windows.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub platforms: BTreeMap<String, PinSet>,
+    /// Platform `anvil lock` ran on.  Recorded package roots are paths on
+    /// that platform, so they're only compared there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
+
+/// Pinned versions of one resolve, with their integrity records.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PinSet {
     /// Pinned versions: package name -> exact version string.
+    #[serde(default)]
     pub pins: HashMap<String, String>,
+    /// Where each pinned package was found and a hash of its definition,
+    /// so `anvil lock --check` can spot in-place edits.  Empty in
+    /// lockfiles written before hashes were recorded.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub integrity: BTreeMap<String, PinIntegrity>,
+}
+
+impl PinSet {
+    /// Pin every package of a resolve, recording its integrity.
+    pub fn from_resolved(packages: &[Package]) -> Result<Self> {
+        let mut set = PinSet::default();
+        for pkg in packages {
+            set.pins.insert(pkg.name.clone(), pkg.version.clone());
+            set.integrity.insert(pkg.name.clone(), PinIntegrity::of(pkg)?);
+        }
+        Ok(set)
+    }
+}
+
+/// Integrity record of one pinned package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinIntegrity {
+    /// Package root the pin resolved to.
+    pub root: PathBuf,
+    /// SHA-256 of the package definition file, hex encoded.
+    pub sha256: String,
+}
+
+impl PinIntegrity {
+    /// Record where `package` lives and hash its definition file.
+    pub fn of(package: &Package) -> Result<Self> {
+        use sha2::{Digest, Sha256};
+
+        let content = std::fs::read(&package.definition)
+            .with_context(|| format!("Failed to read {:?}", package.definition))?;
+        let digest = Sha256::digest(&content);
+        Ok(PinIntegrity {
+            root: package.root.clone(),
+            sha256: digest.iter().map(|b| format!("{:02x}", b)).collect(),
+        })
+    }
 }
 
 impl Lockfile {
+    /// The pin set to use on `platform`.  `None` when the lockfile has
+    /// per-platform sets but none for `platform`.
+    pub fn pin_set(&self, platform: &str) -> Option<&PinSet> {
+        if self.platforms.is_empty() {
+            Some(&self.locked)
+        } else {
+            self.platforms.get(platform)
+        }
+    }
+
+    /// Every pin set with the platform it was resolved for (`None` for the
+    /// single set, which was resolved for `host`).
+    pub fn pin_sets(&self) -> Vec<(Option<&str>, &PinSet)> {
+        if self.platforms.is_empty() {
+            vec![(None, &self.locked)]
+        } else {
+            self.platforms
+                .iter()
+                .map(|(platform, set)| (Some(platform.as_str()), set))
+                .collect()
+        }
+    }
+
     /// Load a lockfile from a YAML file.
     pub fn load(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)
@@ -62,27 +148,72 @@ impl Lockfile {
 /// A fully resolved environment that can be saved, shared, and re-loaded
 /// without re-running resolution.  Useful for render farms, CI, and
 /// sharing reproducible environments across machines.
+///
+/// Only what the packages contribute is stored, as `operations` replayed on
+/// top of whichever machine loads the context.  The saving user's own
+/// environment is kept only when asked for (`base`).
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SavedContext {
     /// Anvil version that created this context.
     pub anvil_version: String,
     /// Unix timestamp (seconds since epoch).
     pub created: u64,
-    /// Platform this was resolved on (linux, macos, windows).
+    /// Platform this was resolved for (linux, macos, windows).
     pub platform: String,
     /// Original package requests.
     pub requests: Vec<String>,
     /// Resolved packages in dependency order.
     pub resolved: Vec<ContextPackage>,
-    /// Full resolved environment (ready to apply).
+    /// Merged command aliases, with the package builtins expanded and
+    /// `${VAR}` references left for the loading machine.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, String>,
+    /// Package environment operations, in the order they apply.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub operations: Vec<EnvOp>,
+    /// The environment the context was resolved on top of, when captured
+    /// with `context save --capture-base`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<HashMap<String, String>>,
+    /// The inherit policy (`--pure`, `allow`, `deny`) in force at save
+    /// time, applied to the loading machine's environment before replay.
+    /// Absent in contexts saved by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherit: Option<InheritConfig>,
+    /// Full resolved environment, as written by older versions.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub environment: HashMap<String, String>,
 }
 
+/// One package-contributed variable assignment.  `value` has the package
+/// builtins (`${PACKAGE_ROOT}`, `${PATHSEP}`, ...) applied; references to
+/// other variables and `~/` are expanded when the operation is replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvOp {
+    pub name: String,
+    pub value: String,
+    /// Package that set it.
+    pub package: String,
+}
+
+/// Apply `operations` in order on top of `base`.
+pub fn apply_operations(operations: &[EnvOp], base: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut env = base.clone();
+    for op in operations {
+        let value = crate::package::expand_vars(&op.value, &env);
+        env.insert(op.name.clone(), value);
+    }
+    env
+}
+
 /// A single resolved package entry inside a saved context.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ContextPackage {
     pub name: String,
     pub version: String,
+    /// Package root (absent in contexts saved by older versions).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
 }
 
 impl SavedContext {
@@ -94,6 +225,73 @@ impl SavedContext {
             .with_context(|| format!("Failed to parse context: {:?}", path))
     }
 
+    /// Whether this context stores a full environment instead of operations.
+    pub fn is_legacy(&self) -> bool {
+        self.operations.is_empty() && !self.environment.is_empty()
+    }
+
+    /// The environment this context produces on top of `base`.  Contexts
+    /// saved by older versions carry their full environment instead.
+    pub fn environment_on(&self, base: &HashMap<String, String>) -> HashMap<String, String> {
+        if self.is_legacy() {
+            return self.environment.clone();
+        }
+        apply_operations(&self.operations, base)
+    }
+
+    /// The loading machine's environment filtered by the saved inherit
+    /// policy, or by `fallback` when the context doesn't carry one.
+    pub fn replay_base(&self, fallback: &InheritConfig) -> HashMap<String, String> {
+        self.inherit.as_ref().unwrap_or(fallback).base_environment()
+    }
+
+    /// The variables this context sets, evaluated on top of `base`.
+    pub fn variables_on(&self, base: &HashMap<String, String>) -> HashMap<String, String> {
+        if self.is_legacy() {
+            return self.environment.clone();
+        }
+        let env = self.environment_on(base);
+        self.operations
+            .iter()
+            .map(|op| (op.name.clone(), env[&op.name].clone()))
+            .collect()
+    }
+
+    /// The command aliases with `${VAR}` references expanded from `env`.
+    pub fn commands_on(&self, env: &HashMap<String, String>) -> HashMap<String, String> {
+        self.commands
+            .iter()
+            .map(|(alias, target)| (alias.clone(), crate::package::expand_vars(target, env)))
+            .collect()
+    }
+
+    /// Translate the paths in this context to how platform `to` mounts
+    /// them, following the config's `path_mappings:`.
+    pub fn remap(&mut self, config: &crate::config::Config, to: &str) {
+        let from = std::mem::replace(&mut self.platform, to.to_string());
+        for op in &mut self.operations {
+            op.value = config.remap_value(&op.value, &from, to);
+        }
+        for target in self.commands.values_mut() {
+            *target = config.remap_value(target, &from, to);
+        }
+        for pkg in &mut self.resolved {
+            let mapped = pkg
+                .root
+                .as_ref()
+                .and_then(|root| config.remap_path(&root.to_string_lossy(), &from, to));
+            if let Some(root) = mapped {
+                pkg.root = Some(PathBuf::from(root));
+            }
+        }
+        let maps = self.base.iter_mut().chain(std::iter::once(&mut self.environment));
+        for env in maps {
+            for value in env.values_mut() {
+                *value = config.remap_value(value, &from, to);
+            }
+        }
+    }
+
     /// Save the context to a JSON file.
     pub fn save(&self, path: &Path) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
@@ -122,3 +320,177 @@ impl SavedContext {
             .as_secs()
     }
 }
+
+// ---------------------------------------------------------------------------
+// Context diff
+// ---------------------------------------------------------------------------
+
+/// Differences between two saved contexts, from `a` to `b`.
+#[derive(Debug, Default, Serialize)]
+pub struct ContextDiff {
+    /// `[a, b]` when the contexts were resolved for different platforms.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<[String; 2]>,
+    /// `[a, b]` when the original requests differ.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests: Option<[Vec<String>; 2]>,
+    pub packages: PackageDiff,
+    pub environment: EnvironmentDiff,
+}
+
+/// Resolved packages only in `a`, only in `b`, or at another version.
+#[derive(Debug, Default, Serialize)]
+pub struct PackageDiff {
+    /// Name -> version, only in `b`.
+    pub added: BTreeMap<String, String>,
+    /// Name -> version, only in `a`.
+    pub removed: BTreeMap<String, String>,
+    /// Name -> `[version in a, version in b]`.
+    pub changed: BTreeMap<String, [String; 2]>,
+}
+
+/// Environment variables only in `a`, only in `b`, or with another value.
+#[derive(Debug, Default, Serialize)]
+pub struct EnvironmentDiff {
+    pub added: BTreeMap<String, String>,
+    pub removed: BTreeMap<String, String>,
+    pub changed: BTreeMap<String, ValueDiff>,
+}
+
+/// A changed variable.  Path lists also get a per-segment breakdown.
+#[derive(Debug, Serialize)]
+pub struct ValueDiff {
+    pub from: String,
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segments: Option<SegmentDiff>,
+}
+
+/// Segment changes of a path-list variable.
+#[derive(Debug, Serialize)]
+pub struct SegmentDiff {
+    /// Segments only in `b`, in `b`'s order.
+    pub added: Vec<String>,
+    /// Segments only in `a`, in `a`'s order.
+    pub removed: Vec<String>,
+    /// Whether the segments both sides share appear in another order.
+    pub reordered: bool,
+}
+
+impl ContextDiff {
+    /// Whether the two contexts resolve to the same thing.
+    pub fn is_empty(&self) -> bool {
+        self.platform.is_none()
+            && self.requests.is_none()
+            && self.packages.added.is_empty()
+            && self.packages.removed.is_empty()
+            && self.packages.changed.is_empty()
+            && self.environment.added.is_empty()
+            && self.environment.removed.is_empty()
+            && self.environment.changed.is_empty()
+    }
+}
+
+impl SavedContext {
+    /// Compare this context (`a`) with `other` (`b`).  Environments are
+    /// compared as each context produces them on its captured base, or on
+    /// an empty one.
+    pub fn diff(&self, other: &SavedContext) -> ContextDiff {
+        let mut diff = ContextDiff::default();
+
+        if self.platform != other.platform {
+            diff.platform = Some([self.platform.clone(), other.platform.clone()]);
+        }
+        if self.requests != other.requests {
+            diff.requests = Some([self.requests.clone(), other.requests.clone()]);
+        }
+
+        let ours: HashMap<&str, &str> = self
+            .resolved
+            .iter()
+            .map(|p| (p.name.as_str(), p.version.as_str()))
+            .collect();
+        let theirs: HashMap<&str, &str> = other
+            .resolved
+            .iter()
+            .map(|p| (p.name.as_str(), p.version.as_str()))
+            .collect();
+        for (name, version) in &ours {
+            match theirs.get(name) {
+                None => {
+                    diff.packages.removed.insert(name.to_string(), version.to_string());
+                }
+                Some(v) if v != version => {
+                    diff.packages
+                        .changed
+                        .insert(name.to_string(), [version.to_string(), v.to_string()]);
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, version) in &theirs {
+            if !ours.contains_key(name) {
+                diff.packages.added.insert(name.to_string(), version.to_string());
+            }
+        }
+
+        let env_a = self.environment_on(self.base.as_ref().unwrap_or(&HashMap::new()));
+        let env_b = other.environment_on(other.base.as_ref().unwrap_or(&HashMap::new()));
+        let (sep_a, sep_b) = (self.pathsep(), other.pathsep());
+        for (key, from) in &env_a {
+            match env_b.get(key) {
+                None => {
+                    diff.environment.removed.insert(key.clone(), from.clone());
+                }
+                Some(to) if to != from => {
+                    let segments = diff_segments(from, sep_a, to, sep_b);
+                    diff.environment.changed.insert(
+                        key.clone(),
+                        ValueDiff {
+                            from: from.clone(),
+                            to: to.clone(),
+                            segments,
+                        },
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        for (key, to) in &env_b {
+            if !env_a.contains_key(key) {
+                diff.environment.added.insert(key.clone(), to.clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Path-list separator of the platform this context was resolved for.
+    fn pathsep(&self) -> &'static str {
+        crate::package::pathsep_for(&self.platform)
+    }
+}
+
+/// Segment-by-segment diff of two values, when both look like path lists:
+/// at least one holds a separator, and every segment is a path.
+fn diff_segments(from: &str, sep_from: &str, to: &str, sep_to: &str) -> Option<SegmentDiff> {
+    if !from.contains(sep_from) && !to.contains(sep_to) {
+        return None;
+    }
+    let a: Vec<&str> = from.split(sep_from).filter(|s| !s.is_empty()).collect();
+    let b: Vec<&str> = to.split(sep_to).filter(|s| !s.is_empty()).collect();
+    if !a.iter().chain(&b).all(|s| crate::package::looks_like_path(s)) {
+        return None;
+    }
+
+    let added: Vec<String> = b.iter().filter(|s| !a.contains(s)).map(|s| s.to_string()).collect();
+    let removed: Vec<String> = a.iter().filter(|s| !b.contains(s)).map(|s| s.to_string()).collect();
+    let common_a: Vec<&&str> = a.iter().filter(|s| b.contains(s)).collect();
+    let common_b: Vec<&&str> = b.iter().filter(|s| a.contains(s)).collect();
+
+    Some(SegmentDiff {
+        added,
+        removed,
+        reordered: common_a != common_b,
+    })
+}
@@ -15,9 +15,10 @@ mod package;
 mod resolver;
 mod shell;
 
-use cli::{Cli, Commands, ContextAction};
+use cli::{AliasAction, CacheAction, Cli, Commands, ConfigAction, ContextAction, IndexAction};
 use config::Config;
-use context::{ContextPackage, Lockfile, SavedContext};
+use context::{ContextPackage, Lockfile, PinIntegrity, PinSet, SavedContext};
+use package::Platform;
 use resolver::Resolver;
 
 fn main() -> Result<()> {
@@ -44,21 +45,33 @@ fn main() -> Result<()> {
         .init();
 
     // Load config
-    let config = Config::load()?;
+    let mut config = Config::load()?;
     let refresh = cli.refresh;
 
     match cli.command {
-        Commands::Env { packages, export, json } => {
-            cmd_env(&config, &packages, export, json, refresh)?;
+        Commands::Env { packages, export, json, format, pure, platform } => {
+            config.inherit.pure |= pure;
+            let platform = target_platform(platform.as_deref())?;
+            let format = env_format(export, json, format);
+            cmd_env(&config, &packages, format.as_deref(), &platform, refresh)?;
         }
-        Commands::Run { packages, env_vars, command } => {
+        Commands::Run { packages, env_vars, pure, command } => {
+            config.inherit.pure |= pure;
             cmd_run(&config, &packages, &env_vars, &command, refresh)?;
         }
-        Commands::Shell { packages, shell, env_only, no_sweep } => {
+        Commands::Shell { packages, shell, env_only, no_sweep, pure } => {
+            config.inherit.pure |= pure;
             cmd_shell(&config, &packages, shell, refresh, env_only, no_sweep)?;
         }
-        Commands::List { package } => {
-            cmd_list(&config, package, refresh)?;
+        Commands::Activate { packages, shell, pure } => {
+            config.inherit.pure |= pure;
+            cmd_activate(&config, &packages, shell, refresh)?;
+        }
+        Commands::Deactivate { shell } => {
+            cmd_deactivate(shell)?;
+        }
+        Commands::List { package, hidden } => {
+            cmd_list(&config, package, hidden, refresh)?;
         }
         Commands::Info { package } => {
             cmd_info(&config, &package, refresh)?;
@@ -66,21 +79,71 @@ fn main() -> Result<()> {
         Commands::Validate { package, strict } => {
             cmd_validate(&config, package, strict, refresh)?;
         }
-        Commands::Lock { packages, update: _ } => {
-            cmd_lock(&config, &packages, refresh)?;
+        Commands::Lock { packages, update, check, platforms } => {
+            if check {
+                cmd_lock_check(&config, &packages, refresh)?;
+            } else if update {
+                cmd_lock_update(&config, &packages, refresh)?;
+            } else {
+                cmd_lock(&config, &packages, &platforms, refresh)?;
+            }
         }
         Commands::Context { action } => match action {
-            ContextAction::Save { packages, output } => {
-                cmd_context_save(&config, &packages, &output, refresh)?;
+            ContextAction::Save { packages, output, pure, platform, capture_base } => {
+                config.inherit.pure |= pure;
+                let platform = target_platform(platform.as_deref())?;
+                cmd_context_save(&config, &packages, &output, &platform, capture_base, refresh)?;
+            }
+            ContextAction::Show { file, json, export, format } => {
+                let format = env_format(export, json, format);
+                cmd_context_show(&config, &file, format.as_deref())?;
+            }
+            ContextAction::Verify { file, resolve } => {
+                cmd_context_verify(&config, &file, resolve, refresh)?;
+            }
+            ContextAction::Remap { file, to, output } => {
+                cmd_context_remap(&config, &file, &to, output)?;
             }
-            ContextAction::Show { file, json, export } => {
-                cmd_context_show(&file, json, export)?;
+            ContextAction::Diff { a, b, json } => {
+                cmd_context_diff(&a, &b, json)?;
             }
-            ContextAction::Run { file, command } => {
-                cmd_context_run(&file, &command)?;
+            ContextAction::Run { file, captured_base, env_vars, command } => {
+                cmd_context_run(&config, &file, captured_base, &env_vars, &command)?;
+            }
+            ContextAction::Shell { file, captured_base, shell, env_only, no_sweep } => {
+                cmd_context_shell(&config, &file, captured_base, shell, env_only, no_sweep)?;
+            }
+        },
+        Commands::Config { action } => match action {
+            ConfigAction::Show { json } => {
+                cmd_config_show(&config, json)?;
+            }
+            ConfigAction::Path => {
+                cmd_config_path(&config);
+            }
+        },
+        Commands::Alias { action } => match action {
+            AliasAction::List => {
+                cmd_alias_list(&config);
             }
-            ContextAction::Shell { file, shell } => {
-                cmd_context_shell(&config, &file, shell)?;
+            AliasAction::Show { name } => {
+                cmd_alias_show(&config, &name)?;
+            }
+        },
+        Commands::Cache { action } => match action {
+            CacheAction::Info => {
+                cmd_cache_info(&config);
+            }
+            CacheAction::Clear => {
+                cmd_cache_clear(&config)?;
+            }
+            CacheAction::Warm { paths } => {
+                cmd_cache_warm(&config, &paths)?;
+            }
+        },
+        Commands::Index { action } => match action {
+            IndexAction::Build { path } => {
+                cmd_index_build(&path)?;
             }
         },
         Commands::Init { name, version, flat, config: scaffold_config } => {
@@ -109,33 +172,57 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-/// Resolve packages and print environment
-fn cmd_env(
-    config: &Config,
-    packages: &[String],
-    export: bool,
-    json: bool,
-    refresh: bool,
-) -> Result<()> {
-    let resolver = Resolver::new(config, refresh)?;
-    let resolved = resolver.resolve(packages)?;
-    let env = resolved.environment();
+/// The `--platform` target, or the host when none is given.
+fn target_platform(spec: Option<&str>) -> Result<Platform> {
+    match spec {
+        Some(spec) => Platform::parse(spec),
+        None => Ok(Platform::current()),
+    }
+}
 
+/// The output format picked by `--export`, `--json` or `--format`; `None`
+/// for plain `KEY=VALUE` lines.
+fn env_format(export: bool, json: bool, format: Option<String>) -> Option<String> {
     if json {
-        println!("{}", serde_json::to_string_pretty(&env)?);
+        Some("json".to_string())
     } else if export {
-        for (key, value) in &env {
-            println!("export {}=\"{}\"", key, value);
-        }
+        Some("sh".to_string())
     } else {
-        for (key, value) in &env {
-            println!("{}={}", key, value);
-        }
+        format
     }
+}
 
+/// Print `env` in `format` (see `env_format`).
+fn print_env(env: &std::collections::HashMap<String, String>, format: Option<&str>) -> Result<()> {
+    match format {
+        Some("json") => println!("{}", serde_json::to_string_pretty(env)?),
+        Some(format) => print!("{}", shell::generate_env_script(format, env)),
+        None => {
+            for (key, value) in env {
+                println!("{}={}", key, value);
+            }
+        }
+    }
     Ok(())
 }
 
+/// Resolve packages and print environment
+fn cmd_env(
+    config: &Config,
+    packages: &[String],
+    format: Option<&str>,
+    platform: &Platform,
+    refresh: bool,
+) -> Result<()> {
+    let resolver = Resolver::new_lazy_for(config, platform, refresh)?;
+    let resolved = resolver.resolve(packages)?;
+    let env = match format {
+        Some(format) if shell::ARTIFACT_FORMATS.contains(&format) => resolved.variables(),
+        _ => resolved.environment(),
+    };
+    print_env(&env, format)
+}
+
 /// Run a command with resolved environment
 fn cmd_run(
     config: &Config,
@@ -144,12 +231,10 @@ fn cmd_run(
     command: &[String],
     refresh: bool,
 ) -> Result<()> {
-    use std::process::Command;
-
     // Pre-resolve hooks
     Config::run_hooks(&config.hooks.pre_resolve, &std::env::vars().collect())?;
 
-    let resolver = Resolver::new(config, refresh)?;
+    let resolver = Resolver::new_lazy(config, refresh)?;
     let resolved = resolver.resolve(packages)?;
     let mut env = resolved.environment();
 
@@ -163,6 +248,17 @@ fn cmd_run(
         }
     }
 
+    let (executable, all_args) = command_argv(&resolved.commands(), command)?;
+
+    exec_command(config, &executable, &all_args, &env)
+}
+
+/// Look `command[0]` up in the `commands` aliases and split the result into
+/// the program and its arguments, followed by the rest of `command`.
+fn command_argv(
+    commands: &std::collections::HashMap<String, String>,
+    command: &[String],
+) -> Result<(String, Vec<String>)> {
     if command.is_empty() {
         anyhow::bail!("No command specified");
     }
@@ -171,8 +267,7 @@ fn cmd_run(
     // containing spaces, e.g. `/Applications/Houdini 20/bin/hython`), or
     // include baked-in arguments (e.g. `nukex: ${NUKE}/Nuke --nukex`), or
     // whitespace from a script launcher (e.g. `python3.14 ~/USD/bin/usdview`).
-    let commands_map = resolved.commands();
-    let resolved_cmd = commands_map
+    let resolved_cmd = commands
         .get(&command[0])
         .cloned()
         .unwrap_or_else(|| command[0].clone());
@@ -187,21 +282,36 @@ fn cmd_run(
     let executable = tokens.remove(0);
     let mut all_args = tokens;
     all_args.extend(command[1..].iter().cloned());
+    Ok((executable, all_args))
+}
+
+/// Run `executable` with exactly `env`, between the run hooks, and exit
+/// with its status.
+fn exec_command(
+    config: &Config,
+    executable: &str,
+    args: &[String],
+    env: &std::collections::HashMap<String, String>,
+) -> Result<()> {
+    use std::process::Command;
 
     // Pre-run hooks
-    Config::run_hooks(&config.hooks.pre_run, &env)?;
+    Config::run_hooks(&config.hooks.pre_run, env)?;
 
     // Surface the resolved argv at `-v`/`-vv` so when an exec fails with
     // "file not found" the user can see what anvil actually tried to run.
-    info!("exec: {} {:?}", executable, all_args);
-
-    let status = Command::new(&executable)
-        .args(&all_args)
-        .envs(&env)
+    info!("exec: {} {:?}", executable, args);
+
+    // `env` is the complete environment (inherited variables included), so
+    // clear first: otherwise denied or `--pure`-stripped variables leak back.
+    let status = Command::new(executable)
+        .args(args)
+        .env_clear()
+        .envs(env)
         .status()?;
 
     // Post-run hooks (best-effort, don't fail on non-zero)
-    let _ = Config::run_hooks(&config.hooks.post_run, &env);
+    let _ = Config::run_hooks(&config.hooks.post_run, env);
 
     std::process::exit(status.code().unwrap_or(1));
 }
@@ -215,14 +325,29 @@ fn cmd_shell(
     env_only: bool,
     no_sweep: bool,
 ) -> Result<()> {
-    let resolver = Resolver::new(config, refresh)?;
+    let resolver = Resolver::new_lazy(config, refresh)?;
     let resolved = resolver.resolve(packages)?;
     let mut env = resolved.environment();
 
     let shell_path = shell
         .or_else(|| config.default_shell.clone())
-        .unwrap_or_else(|| shell::detect_shell());
+        .unwrap_or_else(shell::detect_shell);
+
+    inject_command_shims(config, &mut env, &resolved.commands(), env_only, no_sweep)?;
+    shell::spawn_shell(&shell_path, &env)?;
+
+    Ok(())
+}
 
+/// Materialise `commands` as PATH shims in `env`, unless shims are turned
+/// off.
+fn inject_command_shims(
+    config: &Config,
+    env: &mut std::collections::HashMap<String, String>,
+    commands: &std::collections::HashMap<String, String>,
+    env_only: bool,
+    no_sweep: bool,
+) -> Result<()> {
     // Opt-outs, in priority order:
     //   1. --env-only flag
     //   2. ANVIL_DISABLE_COMMAND_SHIMS env var (useful in CI)
@@ -235,10 +360,9 @@ fn cmd_shell(
             shell::sweep_stale_shims(std::time::Duration::from_secs(config.shell.orphan_ttl));
         }
 
-        let commands = resolved.commands();
         if !commands.is_empty() {
-            let shim_dir = shell::materialize_commands(&commands)?;
-            shell::prepend_path(&mut env, &shim_dir);
+            let shim_dir = shell::materialize_commands(commands)?;
+            shell::prepend_path(env, &shim_dir);
             env.insert(
                 "ANVIL_COMMAND_DIR".to_string(),
                 shim_dir.to_string_lossy().into_owned(),
@@ -246,16 +370,26 @@ fn cmd_shell(
         }
     }
 
-    shell::spawn_shell(&shell_path, &env)?;
-
     Ok(())
 }
 
 /// List available packages
-fn cmd_list(config: &Config, package: Option<String>, refresh: bool) -> Result<()> {
+fn cmd_list(config: &Config, package: Option<String>, hidden: bool, refresh: bool) -> Result<()> {
     let resolver = Resolver::new(config, refresh)?;
 
-    if let Some(name) = package {
+    if hidden {
+        let entries: Vec<_> = resolver
+            .hidden_packages()
+            .into_iter()
+            .filter(|h| package.as_ref().is_none_or(|n| &h.name == n))
+            .collect();
+        if entries.is_empty() {
+            eprintln!("No packages hidden by filters.");
+        }
+        for h in entries {
+            println!("{}-{}  ({})", h.name, h.version, h.rule);
+        }
+    } else if let Some(name) = package {
         // List versions of specific package
         let versions = resolver.list_versions(&name)?;
         println!("{}:", name);
@@ -325,6 +459,26 @@ fn cmd_info(config: &Config, package: &str, refresh: bool) -> Result<()> {
             println!("  {}: {}", alias, target);
         }
     }
+    let applied = resolver.applied_overrides(&pkg.id());
+    if !applied.is_empty() {
+        println!("Overrides (from config):");
+        for key in &applied {
+            let ovr = &config.overrides[key];
+            match config.provenance.value(&format!("overrides.{}", key)) {
+                Some(origin) => println!("  {}  # {}", key, origin),
+                None => println!("  {}", key),
+            }
+            for (k, v) in &ovr.environment {
+                println!("    environment: {}={}", k, v);
+            }
+            for req in &ovr.requires {
+                println!("    requires: {}", req);
+            }
+            for (alias, target) in &ovr.commands {
+                println!("    commands: {}={}", alias, target);
+            }
+        }
+    }
 
     Ok(())
 }
@@ -393,33 +547,329 @@ fn cmd_validate(
 }
 
 // ---------------------------------------------------------------------------
-// Lock
+// Activate
 // ---------------------------------------------------------------------------
 
-/// Resolve packages and write pinned versions to `anvil.lock`.
-fn cmd_lock(config: &Config, packages: &[String], refresh: bool) -> Result<()> {
-    // Always resolve fresh (ignore existing lockfile).
-    let resolver = Resolver::new_unlocked(config, refresh)?;
+/// Variable holding what `anvil activate` replaced: a JSON object of
+/// variable -> previous value, `null` for previously unset.
+const ACTIVE_STATE_VAR: &str = "ANVIL_ACTIVE_STATE";
+/// Variable naming the packages of the active environment.
+const ACTIVE_VAR: &str = "ANVIL_ACTIVE";
+
+/// The shell to write activation code for, checked against the ones
+/// supported.
+fn activation_shell(shell: Option<String>) -> Result<String> {
+    let shell = shell.unwrap_or_else(shell::detect_shell);
+    let name = shell::shell_name(&shell).to_string();
+    if !shell::ACTIVATE_SHELLS.contains(&name.as_str()) {
+        anyhow::bail!(
+            "Can't activate in {} (supported: {})",
+            name,
+            shell::ACTIVATE_SHELLS.join(", ")
+        );
+    }
+    Ok(name)
+}
+
+/// The previous values recorded by an earlier `anvil activate`.
+fn active_state() -> Result<Option<std::collections::BTreeMap<String, Option<String>>>> {
+    match std::env::var(ACTIVE_STATE_VAR) {
+        Ok(state) => serde_json::from_str(&state)
+            .map(Some)
+            .with_context(|| format!("Malformed {}", ACTIVE_STATE_VAR)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Print shell code that moves the current shell to the resolved
+/// environment and records what it replaces.  Activating again first
+/// undoes the previous activation.
+fn cmd_activate(config: &Config, packages: &[String], shell: Option<String>, refresh: bool) -> Result<()> {
+    let shell = activation_shell(shell)?;
+
+    // Resolve from the shell's state before any earlier activation, so
+    // re-activating doesn't stack on top of it.
+    let earlier = active_state()?.unwrap_or_default();
+    for (key, value) in &earlier {
+        match value {
+            Some(value) => std::env::set_var(key, value),
+            None => std::env::remove_var(key),
+        }
+    }
+    std::env::remove_var(ACTIVE_STATE_VAR);
+    std::env::remove_var(ACTIVE_VAR);
+    let original: std::collections::HashMap<String, String> = std::env::vars().collect();
+
+    let resolver = Resolver::new_lazy(config, refresh)?;
     let resolved = resolver.resolve(packages)?;
+    let env = resolved.environment();
 
-    let mut pins = std::collections::HashMap::new();
-    for pkg in resolved.packages() {
-        pins.insert(pkg.name.clone(), pkg.version.clone());
+    let mut changes = std::collections::BTreeMap::new();
+    let mut previous = std::collections::BTreeMap::new();
+    for (key, value) in &env {
+        if original.get(key) != Some(value) {
+            changes.insert(key.clone(), Some(value.clone()));
+            previous.insert(key.clone(), original.get(key).cloned());
+        }
+    }
+    // Variables the inherit policy dropped.
+    for (key, value) in &original {
+        if !env.contains_key(key) {
+            changes.insert(key.clone(), None);
+            previous.insert(key.clone(), Some(value.clone()));
+        }
+    }
+    // Whatever the earlier activation changed and this one leaves alone
+    // goes back to how it was.
+    for (key, value) in earlier {
+        changes.entry(key).or_insert(value);
     }
 
-    let lockfile = Lockfile {
+    changes.insert(ACTIVE_STATE_VAR.to_string(), Some(serde_json::to_string(&previous)?));
+    changes.insert(ACTIVE_VAR.to_string(), Some(packages.join(" ")));
+    print!("{}", shell::generate_env_changes(&shell, &changes));
+
+    Ok(())
+}
+
+/// Print shell code restoring every variable `anvil activate` changed,
+/// unsetting the ones that weren't set before.
+fn cmd_deactivate(shell: Option<String>) -> Result<()> {
+    let shell = activation_shell(shell)?;
+    let Some(mut changes) = active_state()? else {
+        anyhow::bail!("No anvil environment is active in this shell");
+    };
+    changes.insert(ACTIVE_STATE_VAR.to_string(), None);
+    changes.insert(ACTIVE_VAR.to_string(), None);
+    print!("{}", shell::generate_env_changes(&shell, &changes));
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Lock
+// ---------------------------------------------------------------------------
+
+/// Resolve packages and write pinned versions to `anvil.lock`.  With
+/// `platforms`, resolve once per platform and store a pin set for each.
+fn cmd_lock(config: &Config, packages: &[String], platforms: &[String], refresh: bool) -> Result<()> {
+    let mut lockfile = Lockfile {
         requests: packages.to_vec(),
-        pins,
+        locked: PinSet::default(),
+        platforms: std::collections::BTreeMap::new(),
+        host: Some(SavedContext::current_platform().to_string()),
+    };
+
+    // Always resolve fresh (ignore existing lockfile).
+    if platforms.is_empty() {
+        let resolver = Resolver::new_unlocked(config, refresh)?;
+        let resolved = resolver.resolve(packages)?;
+        lockfile.locked = PinSet::from_resolved(resolved.packages())?;
+
+        println!("Locked {} packages to anvil.lock:", resolved.packages().len());
+        for pkg in resolved.packages() {
+            println!("  {}-{}", pkg.name, pkg.version);
+        }
+    } else {
+        println!("Locked anvil.lock for {}:", platforms.join(", "));
+        for platform in platforms {
+            let target = Platform::parse(platform)?;
+            let resolver =
+                Resolver::with_pins(config, std::collections::HashMap::new(), &target, refresh)?;
+            let resolved = resolver
+                .resolve(packages)
+                .with_context(|| format!("Failed to resolve for {}", platform))?;
+            println!("  {}:", platform);
+            for pkg in resolved.packages() {
+                println!("    {}-{}", pkg.name, pkg.version);
+            }
+            lockfile
+                .platforms
+                .insert(platform.clone(), PinSet::from_resolved(resolved.packages())?);
+        }
+    }
+
+    lockfile.save(std::path::Path::new("anvil.lock"))?;
+    Ok(())
+}
+
+/// Re-resolve the existing lockfile's requests, for every platform it
+/// holds pins for.  Pins of packages not in `names` are kept; with no
+/// names every pin is released.
+fn cmd_lock_update(config: &Config, names: &[String], refresh: bool) -> Result<()> {
+    let lock_path = Lockfile::find()
+        .ok_or_else(|| anyhow::anyhow!("No anvil.lock found (run `anvil lock <packages>` first)"))?;
+    let old = Lockfile::load(&lock_path)?;
+    let old_sets = old.pin_sets();
+
+    for name in names {
+        if !old_sets.iter().any(|(_, set)| set.pins.contains_key(name)) {
+            anyhow::bail!("{} is not pinned in {}", name, lock_path.display());
+        }
+    }
+
+    let host = SavedContext::current_platform();
+    let mut new = Lockfile {
+        requests: old.requests.clone(),
+        locked: PinSet::default(),
+        platforms: std::collections::BTreeMap::new(),
+        host: Some(host.to_string()),
+    };
+    let mut changes = Vec::new();
+
+    for (platform, set) in old_sets {
+        let kept = if names.is_empty() {
+            std::collections::HashMap::new()
+        } else {
+            set.pins
+                .iter()
+                .filter(|(name, _)| !names.contains(name))
+                .map(|(name, version)| (name.clone(), version.clone()))
+                .collect()
+        };
+
+        let target = Platform::parse(platform.unwrap_or(host))?;
+        let resolver = Resolver::with_pins(config, kept, &target, refresh)?;
+        let resolved = resolver.resolve(&old.requests)?;
+        let fresh = PinSet::from_resolved(resolved.packages())?;
+
+        let prefix = platform.map(|p| format!("[{}] ", p)).unwrap_or_default();
+        for change in pin_changes(&set.pins, &fresh.pins) {
+            changes.push(format!("  {}{}", prefix, change));
+        }
+        match platform {
+            Some(p) => {
+                new.platforms.insert(p.to_string(), fresh);
+            }
+            None => new.locked = fresh,
+        }
+    }
+    new.save(&lock_path)?;
+
+    if changes.is_empty() {
+        println!("{} is already up to date", lock_path.display());
+    } else {
+        println!("Updated {}:", lock_path.display());
+        for change in changes {
+            println!("{}", change);
+        }
+    }
+    Ok(())
+}
+
+/// Describe how pins changed, one line per package, sorted by name.
+fn pin_changes(
+    old: &std::collections::HashMap<String, String>,
+    new: &std::collections::HashMap<String, String>,
+) -> Vec<String> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter_map(|name| match (old.get(name), new.get(name)) {
+            (Some(a), Some(b)) if a != b => Some(format!("{}  {} -> {}", name, a, b)),
+            (Some(a), None) => Some(format!("{}  {} -> (removed)", name, a)),
+            (None, Some(b)) => Some(format!("{}  (new) -> {}", name, b)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Check `anvil.lock` against the packages on disk, for CI.  Fails when
+/// the requests differ, a resolved package isn't pinned (or its pin is
+/// gone), or a pinned definition moved or changed since it was locked.
+/// Every platform pin set is checked; package roots only when this is the
+/// platform the lock was made on.
+fn cmd_lock_check(config: &Config, packages: &[String], refresh: bool) -> Result<()> {
+    let lock_path = Lockfile::find()
+        .ok_or_else(|| anyhow::anyhow!("No anvil.lock found (run `anvil lock` first)"))?;
+    let lockfile = Lockfile::load(&lock_path)?;
+    let requests = if packages.is_empty() {
+        lockfile.requests.clone()
+    } else {
+        packages.to_vec()
     };
 
-    let lock_path = std::path::PathBuf::from("anvil.lock");
-    lockfile.save(&lock_path)?;
+    let mut problems = Vec::new();
+    if requests != lockfile.requests {
+        problems.push(format!(
+            "requests changed: locked [{}], now [{}]",
+            lockfile.requests.join(" "),
+            requests.join(" ")
+        ));
+    }
+
+    let host = SavedContext::current_platform();
+    let compare_roots = lockfile.host.as_deref().is_none_or(|h| h == host);
+    let mut counts = Vec::new();
+
+    for (platform, set) in lockfile.pin_sets() {
+        let prefix = platform.map(|p| format!("[{}] ", p)).unwrap_or_default();
+        let target = Platform::parse(platform.unwrap_or(host))?;
+        let resolver = Resolver::with_pins(config, set.pins.clone(), &target, refresh)?;
+        let resolved = resolver.resolve(&requests)?;
+        counts.push(match platform {
+            Some(p) => format!("{} on {}", resolved.packages().len(), p),
+            None => resolved.packages().len().to_string(),
+        });
+
+        let mut required = std::collections::HashSet::new();
+        for pkg in resolved.packages() {
+            required.insert(pkg.name.as_str());
+            match set.pins.get(&pkg.name) {
+                None => problems.push(format!("{}{}: not pinned", prefix, pkg.id())),
+                Some(pinned) if *pinned != pkg.version => problems.push(format!(
+                    "{}{}-{}: pinned version not available, resolves to {}",
+                    prefix, pkg.name, pinned, pkg.version
+                )),
+                Some(_) => {}
+            }
+
+            let Some(locked) = set.integrity.get(&pkg.name) else {
+                problems.push(format!("{}{}: no integrity hash recorded", prefix, pkg.id()));
+                continue;
+            };
+            let current = PinIntegrity::of(pkg)?;
+            if compare_roots && current.root != locked.root {
+                problems.push(format!(
+                    "{}{}: moved from {} to {}",
+                    prefix,
+                    pkg.id(),
+                    locked.root.display(),
+                    current.root.display()
+                ));
+            }
+            if current.sha256 != locked.sha256 {
+                problems.push(format!(
+                    "{}{}: {} changed since it was locked",
+                    prefix,
+                    pkg.id(),
+                    pkg.definition.display()
+                ));
+            }
+        }
+
+        let mut stale: Vec<_> = set
+            .pins
+            .iter()
+            .filter(|(name, _)| !required.contains(name.as_str()))
+            .collect();
+        stale.sort();
+        for (name, version) in stale {
+            problems.push(format!("{}{}-{}: pinned but no longer required", prefix, name, version));
+        }
+    }
 
-    println!("Locked {} packages to anvil.lock:", resolved.packages().len());
-    for pkg in resolved.packages() {
-        println!("  {}-{}", pkg.name, pkg.version);
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "{} has drifted:\n  - {}",
+            lock_path.display(),
+            problems.join("\n  - ")
+        );
     }
 
+    println!("{} is up to date ({} packages)", lock_path.display(), counts.join(", "));
     Ok(())
 }
 
@@ -432,16 +882,17 @@ fn cmd_context_save(
     config: &Config,
     packages: &[String],
     output: &str,
+    platform: &Platform,
+    capture_base: bool,
     refresh: bool,
 ) -> Result<()> {
-    let resolver = Resolver::new(config, refresh)?;
+    let resolver = Resolver::new_lazy_for(config, platform, refresh)?;
     let resolved = resolver.resolve(packages)?;
-    let env = resolved.environment();
 
     let ctx = SavedContext {
         anvil_version: env!("CARGO_PKG_VERSION").to_string(),
         created: SavedContext::now(),
-        platform: SavedContext::current_platform().to_string(),
+        platform: platform.os.clone(),
         requests: packages.to_vec(),
         resolved: resolved
             .packages()
@@ -449,9 +900,14 @@ fn cmd_context_save(
             .map(|p| ContextPackage {
                 name: p.name.clone(),
                 version: p.version.clone(),
+                root: Some(p.root.clone()),
             })
             .collect(),
-        environment: env,
+        commands: resolved.command_templates(),
+        operations: resolved.operations(),
+        base: capture_base.then(|| resolved.base().clone()),
+        inherit: Some(config.inherit.clone()),
+        environment: std::collections::HashMap::new(),
     };
 
     let path = std::path::Path::new(output);
@@ -465,16 +921,14 @@ fn cmd_context_save(
     Ok(())
 }
 
-/// Display the environment from a saved context file.
-fn cmd_context_show(file: &str, json: bool, export: bool) -> Result<()> {
-    let ctx = SavedContext::load(std::path::Path::new(file))?;
+/// Display the environment from a saved context file.  `--json`,
+/// `--export` and `--format` print the variables the context sets,
+/// evaluated on top of this machine's environment.
+fn cmd_context_show(config: &Config, file: &str, format: Option<&str>) -> Result<()> {
+    let ctx = load_context(config, file)?;
 
-    if json {
-        println!("{}", serde_json::to_string_pretty(&ctx.environment)?);
-    } else if export {
-        for (key, value) in &ctx.environment {
-            println!("export {}=\"{}\"", key, value);
-        }
+    if format.is_some() {
+        print_env(&ctx.variables_on(&ctx.replay_base(&config.inherit)), format)?;
     } else {
         println!(
             "Context: {} packages, platform={}, anvil={}",
@@ -486,46 +940,710 @@ fn cmd_context_show(file: &str, json: bool, export: bool) -> Result<()> {
         for pkg in &ctx.resolved {
             println!("  {}-{}", pkg.name, pkg.version);
         }
-        println!("Environment ({} variables):", ctx.environment.len());
-        for (key, value) in &ctx.environment {
-            println!("  {}={}", key, value);
+        if ctx.is_legacy() {
+            println!("Environment ({} variables):", ctx.environment.len());
+            for (key, value) in &ctx.environment {
+                println!("  {}={}", key, value);
+            }
+        } else {
+            println!("Operations ({}):", ctx.operations.len());
+            for op in &ctx.operations {
+                println!("  {}={}  ({})", op.name, op.value, op.package);
+            }
+            if let Some(inherit) = &ctx.inherit {
+                let mode = if inherit.pure { "pure" } else { "caller environment" };
+                println!("Inherit: {}", mode);
+            }
+            if let Some(base) = &ctx.base {
+                println!("Captured base: {} variables", base.len());
+            }
         }
     }
 
     Ok(())
 }
 
-/// Run a command using a saved context's environment.
-fn cmd_context_run(file: &str, command: &[String]) -> Result<()> {
-    use std::process::Command;
+/// Load a saved context, translating its paths with `path_mappings:` when
+/// it was resolved for another platform.
+fn load_context(config: &Config, file: &str) -> Result<SavedContext> {
+    let mut ctx = SavedContext::load(std::path::Path::new(file))?;
+    let host = SavedContext::current_platform();
+    if ctx.platform != host {
+        info!("Remapping {} from {} to {}", file, ctx.platform, host);
+        ctx.remap(config, host);
+    }
+    Ok(ctx)
+}
 
-    if command.is_empty() {
-        anyhow::bail!("No command specified");
+/// `context verify` exit status bits, one per kind of problem.
+const VERIFY_MISSING_ROOT: i32 = 2;
+const VERIFY_BAD_COMMAND: i32 = 4;
+const VERIFY_PLATFORM: i32 = 8;
+const VERIFY_STALE: i32 = 16;
+
+/// Check that a saved context can still be used on this machine: package
+/// roots exist, command targets are executable, the platform matches and,
+/// with `resolve`, that its requests still resolve to the same versions.
+/// Exits with the `VERIFY_*` bits of every kind of problem found.
+fn cmd_context_verify(config: &Config, file: &str, resolve: bool, refresh: bool) -> Result<()> {
+    let saved_for = SavedContext::load(std::path::Path::new(file))?.platform;
+    let ctx = load_context(config, file)?;
+    let host = SavedContext::current_platform();
+    let mut problems: Vec<(i32, String)> = Vec::new();
+
+    if saved_for != host {
+        problems.push((
+            VERIFY_PLATFORM,
+            format!("resolved for {}, this machine is {}", saved_for, host),
+        ));
     }
 
-    let ctx = SavedContext::load(std::path::Path::new(file))?;
+    for pkg in &ctx.resolved {
+        if let Some(root) = &pkg.root {
+            if !root.exists() {
+                problems.push((
+                    VERIFY_MISSING_ROOT,
+                    format!("{}-{}: root {} does not exist", pkg.name, pkg.version, root.display()),
+                ));
+            }
+        }
+    }
 
-    let status = Command::new(&command[0])
-        .args(&command[1..])
-        .envs(&ctx.environment)
-        .status()?;
+    let env = ctx.environment_on(&ctx.replay_base(&config.inherit));
+    let cwd = std::env::current_dir()?;
+    let mut commands: Vec<_> = ctx.commands_on(&env).into_iter().collect();
+    commands.sort();
+    for (alias, target) in &commands {
+        let tokens = match package::tokenize_command(target) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                problems.push((VERIFY_BAD_COMMAND, format!("{}: failed to parse ({})", alias, e)));
+                continue;
+            }
+        };
+        let Some(program) = tokens.first() else {
+            problems.push((VERIFY_BAD_COMMAND, format!("{}: alias resolved to empty string", alias)));
+            continue;
+        };
+        // Bare names are looked up on the context's PATH, not anvil's.
+        let program = which::which_in(program, env.get("PATH"), &cwd)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| program.clone());
+        if let Err(msg) = resolver::check_executable(&program) {
+            problems.push((VERIFY_BAD_COMMAND, format!("{} -> {:?}: {}", alias, program, msg)));
+        }
+    }
 
-    std::process::exit(status.code().unwrap_or(1));
+    if resolve {
+        let platform = Platform::parse(&saved_for)?;
+        let resolver = Resolver::new_lazy_for(config, &platform, refresh)?;
+        // A request that no longer resolves at all (say, a removed
+        // package) is as stale as one resolving to other versions.
+        match resolver.resolve(&ctx.requests) {
+            Err(e) => problems.push((
+                VERIFY_STALE,
+                format!("{} no longer resolves: {:#}", ctx.requests.join(" "), e),
+            )),
+            Ok(resolved) => {
+                let stored: std::collections::HashMap<&str, &str> = ctx
+                    .resolved
+                    .iter()
+                    .map(|p| (p.name.as_str(), p.version.as_str()))
+                    .collect();
+                for pkg in resolved.packages() {
+                    match stored.get(pkg.name.as_str()) {
+                        Some(version) if *version == pkg.version => {}
+                        Some(version) => problems.push((
+                            VERIFY_STALE,
+                            format!(
+                                "{} now resolves to {} (context has {})",
+                                pkg.name, pkg.version, version
+                            ),
+                        )),
+                        None => problems.push((
+                            VERIFY_STALE,
+                            format!(
+                                "{}-{} is now required but not in the context",
+                                pkg.name, pkg.version
+                            ),
+                        )),
+                    }
+                }
+                let current: std::collections::HashSet<&str> =
+                    resolved.packages().iter().map(|p| p.name.as_str()).collect();
+                for pkg in &ctx.resolved {
+                    if !current.contains(pkg.name.as_str()) {
+                        problems.push((
+                            VERIFY_STALE,
+                            format!("{}-{} is no longer required", pkg.name, pkg.version),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!(
+            "{} is usable ({} packages, {} commands)",
+            file,
+            ctx.resolved.len(),
+            commands.len()
+        );
+        return Ok(());
+    }
+
+    eprintln!("{} has problems:", file);
+    let mut code = 0;
+    for (kind, problem) in &problems {
+        let label = match *kind {
+            VERIFY_MISSING_ROOT => "missing root",
+            VERIFY_BAD_COMMAND => "command",
+            VERIFY_PLATFORM => "platform",
+            _ => "stale",
+        };
+        eprintln!("  [{}] {}", label, problem);
+        code |= kind;
+    }
+    std::process::exit(code);
+}
+
+/// Write a copy of a saved context with its paths translated for another
+/// platform.
+fn cmd_context_remap(config: &Config, file: &str, to: &str, output: Option<String>) -> Result<()> {
+    let mut ctx = SavedContext::load(std::path::Path::new(file))?;
+    let from = ctx.platform.clone();
+    ctx.remap(config, to);
+
+    let output = output.unwrap_or_else(|| {
+        let stem = file.strip_suffix(".json").unwrap_or(file);
+        format!("{}.{}.json", stem, to)
+    });
+    ctx.save(std::path::Path::new(&output))?;
+    println!("Remapped {} ({} -> {}) to {}", file, from, to, output);
+
+    Ok(())
+}
+
+/// The environment a saved context runs with: its operations replayed on
+/// this machine's environment, or on the one captured at save time.
+fn context_environment(
+    config: &Config,
+    ctx: &SavedContext,
+    file: &str,
+    captured_base: bool,
+) -> Result<std::collections::HashMap<String, String>> {
+    if !captured_base {
+        return Ok(ctx.environment_on(&ctx.replay_base(&config.inherit)));
+    }
+    match &ctx.base {
+        Some(base) => Ok(ctx.environment_on(base)),
+        None => anyhow::bail!(
+            "{} has no captured base (save it with `anvil context save --capture-base`)",
+            file
+        ),
+    }
+}
+
+/// Compare two saved contexts.
+fn cmd_context_diff(a: &str, b: &str, json: bool) -> Result<()> {
+    let ctx_a = SavedContext::load(std::path::Path::new(a))?;
+    let ctx_b = SavedContext::load(std::path::Path::new(b))?;
+    let diff = ctx_a.diff(&ctx_b);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    if diff.is_empty() {
+        println!("{} and {} are identical", a, b);
+        return Ok(());
+    }
+
+    println!("--- {}", a);
+    println!("+++ {}", b);
+    if let Some([from, to]) = &diff.platform {
+        println!("Platform: {} -> {}", from, to);
+    }
+    if let Some([from, to]) = &diff.requests {
+        println!("Requests: {} -> {}", from.join(" "), to.join(" "));
+    }
+
+    let packages = &diff.packages;
+    if !(packages.added.is_empty() && packages.removed.is_empty() && packages.changed.is_empty()) {
+        println!("Packages:");
+        for (name, version) in &packages.added {
+            println!("  + {}-{}", name, version);
+        }
+        for (name, version) in &packages.removed {
+            println!("  - {}-{}", name, version);
+        }
+        for (name, [from, to]) in &packages.changed {
+            println!("  ~ {}  {} -> {}", name, from, to);
+        }
+    }
+
+    let env = &diff.environment;
+    if !(env.added.is_empty() && env.removed.is_empty() && env.changed.is_empty()) {
+        println!("Environment:");
+        for (key, value) in &env.added {
+            println!("  + {}={}", key, value);
+        }
+        for (key, value) in &env.removed {
+            println!("  - {}={}", key, value);
+        }
+        for (key, change) in &env.changed {
+            match &change.segments {
+                Some(segments) => {
+                    println!("  ~ {}:", key);
+                    for segment in &segments.added {
+                        println!("      + {}", segment);
+                    }
+                    for segment in &segments.removed {
+                        println!("      - {}", segment);
+                    }
+                    if segments.reordered {
+                        println!("      (order changed)");
+                    }
+                }
+                None => println!("  ~ {}: {} -> {}", key, change.from, change.to),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a command using a saved context's environment, resolving command
+/// aliases like `anvil run`.
+fn cmd_context_run(
+    config: &Config,
+    file: &str,
+    captured_base: bool,
+    env_vars: &[String],
+    command: &[String],
+) -> Result<()> {
+    let ctx = load_context(config, file)?;
+    let mut env = context_environment(config, &ctx, file, captured_base)?;
+    let commands = ctx.commands_on(&env);
+
+    for var in env_vars {
+        if let Some((key, value)) = var.split_once('=') {
+            env.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    let (executable, args) = command_argv(&commands, command)?;
+    exec_command(config, &executable, &args, &env)
 }
 
-/// Start a shell with a saved context's environment.
-fn cmd_context_shell(config: &Config, file: &str, shell_override: Option<String>) -> Result<()> {
-    let ctx = SavedContext::load(std::path::Path::new(file))?;
+/// Start a shell with a saved context's environment and command shims,
+/// like `anvil shell`.
+fn cmd_context_shell(
+    config: &Config,
+    file: &str,
+    captured_base: bool,
+    shell_override: Option<String>,
+    env_only: bool,
+    no_sweep: bool,
+) -> Result<()> {
+    let ctx = load_context(config, file)?;
+    let mut env = context_environment(config, &ctx, file, captured_base)?;
 
     let shell_path = shell_override
         .or_else(|| config.default_shell.clone())
-        .unwrap_or_else(|| shell::detect_shell());
+        .unwrap_or_else(shell::detect_shell);
+
+    let commands = ctx.commands_on(&env);
+    inject_command_shims(config, &mut env, &commands, env_only, no_sweep)?;
+    shell::spawn_shell(&shell_path, &env)?;
+
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Config
+// ---------------------------------------------------------------------------
+
+/// Print the effective config with the origin of every value.
+fn cmd_config_show(config: &Config, json: bool) -> Result<()> {
+    use serde_json::json;
+
+    let prov = &config.provenance;
+    let origin_json = |origin: Option<&config::Origin>| match origin {
+        Some(o) => json!({ "layer": o.layer, "file": o.file }),
+        None => json!({ "layer": config::Layer::Default, "file": null }),
+    };
+    let list_json = |key: &str, values: &[String]| {
+        let origins = prov.list(key);
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| json!({ "value": v, "origin": origin_json(origins.get(i)) }))
+            .collect::<Vec<_>>()
+    };
+
+    if json {
+        let mut aliases = serde_json::Map::new();
+        for (name, members) in sorted(&config.aliases) {
+            aliases.insert(
+                name.clone(),
+                json!({
+                    "value": members,
+                    "origin": origin_json(prov.value(&format!("aliases.{}", name))),
+                }),
+            );
+        }
+        let mut overrides = serde_json::Map::new();
+        for (key, ovr) in &config.overrides {
+            overrides.insert(
+                key.clone(),
+                json!({
+                    "value": ovr,
+                    "origin": origin_json(prov.value(&format!("overrides.{}", key))),
+                }),
+            );
+        }
+        let out = json!({
+            "files": prov.files,
+            "package_paths": list_json("package_paths", &config.package_paths),
+            "default_shell": {
+                "value": config.default_shell,
+                "origin": origin_json(prov.value("default_shell")),
+            },
+            "aliases": aliases,
+            "hooks": {
+                "pre_resolve": list_json("hooks.pre_resolve", &config.hooks.pre_resolve),
+                "post_resolve": list_json("hooks.post_resolve", &config.hooks.post_resolve),
+                "pre_run": list_json("hooks.pre_run", &config.hooks.pre_run),
+                "post_run": list_json("hooks.post_run", &config.hooks.post_run),
+            },
+            "filters": {
+                "value": config.filters,
+                "origin": origin_json(prov.value("filters")),
+            },
+            "shell": {
+                "value": config.shell,
+                "origin": origin_json(prov.value("shell")),
+            },
+            "inherit": {
+                "value": config.inherit,
+                "origin": origin_json(prov.value("inherit")),
+            },
+            "overrides": overrides,
+            "cache_dir": {
+                "value": config.cache_dir,
+                "origin": origin_json(prov.value("cache_dir")),
+            },
+            "require_package_paths": {
+                "value": config.require_package_paths,
+                "origin": origin_json(prov.value("require_package_paths")),
+            },
+            "path_mappings": config
+                .path_mappings
+                .iter()
+                .enumerate()
+                .map(|(i, m)| json!({ "value": m, "origin": origin_json(prov.list("path_mappings").get(i)) }))
+                .collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    let origin_str = |origin: Option<&config::Origin>| match origin {
+        Some(o) => o.to_string(),
+        None => config::Layer::Default.to_string(),
+    };
+    let print_list = |indent: &str, key: &str, values: &[String]| {
+        let origins = prov.list(key);
+        for (i, v) in values.iter().enumerate() {
+            println!("{}- {}  # {}", indent, v, origin_str(origins.get(i)));
+        }
+    };
+
+    println!("package_paths:");
+    print_list("  ", "package_paths", &config.package_paths);
+
+    match &config.default_shell {
+        Some(sh) => println!("default_shell: {}  # {}", sh, origin_str(prov.value("default_shell"))),
+        None => println!("default_shell: ~  # default"),
+    }
+
+    println!("aliases:");
+    for (name, members) in sorted(&config.aliases) {
+        println!(
+            "  {}: [{}]  # {}",
+            name,
+            members.join(", "),
+            origin_str(prov.value(&format!("aliases.{}", name)))
+        );
+    }
+
+    println!("hooks:");
+    let hooks = [
+        ("pre_resolve", &config.hooks.pre_resolve),
+        ("post_resolve", &config.hooks.post_resolve),
+        ("pre_run", &config.hooks.pre_run),
+        ("post_run", &config.hooks.post_run),
+    ];
+    for (name, cmds) in hooks {
+        if cmds.is_empty() {
+            continue;
+        }
+        println!("  {}:", name);
+        print_list("    ", &format!("hooks.{}", name), cmds);
+    }
+
+    println!("filters:  # {}", origin_str(prov.value("filters")));
+    println!("  include: [{}]", config.filters.include.join(", "));
+    println!("  exclude: [{}]", config.filters.exclude.join(", "));
+
+    println!("shell:  # {}", origin_str(prov.value("shell")));
+    println!("  inject_commands: {}", config.shell.inject_commands);
+    println!("  orphan_ttl: {}", config.shell.orphan_ttl);
+
+    println!("inherit:  # {}", origin_str(prov.value("inherit")));
+    println!("  pure: {}", config.inherit.pure);
+    println!("  allow: [{}]", config.inherit.allow.join(", "));
+    println!("  deny: [{}]", config.inherit.deny.join(", "));
+
+    println!("overrides:");
+    for (key, ovr) in &config.overrides {
+        println!("  {}:  # {}", key, origin_str(prov.value(&format!("overrides.{}", key))));
+        for (k, v) in &ovr.environment {
+            println!("    environment: {}={}", k, v);
+        }
+        for req in &ovr.requires {
+            println!("    requires: {}", req);
+        }
+        for (alias, target) in &ovr.commands {
+            println!("    commands: {}={}", alias, target);
+        }
+    }
+
+    match &config.cache_dir {
+        Some(dir) => println!("cache_dir: {}  # {}", dir, origin_str(prov.value("cache_dir"))),
+        None => println!("cache_dir: ~  # default"),
+    }
+
+    match config.require_package_paths {
+        Some(required) => println!(
+            "require_package_paths: {}  # {}",
+            required,
+            origin_str(prov.value("require_package_paths"))
+        ),
+        None => println!("require_package_paths: false  # default"),
+    }
+
+    println!("path_mappings:");
+    let origins = prov.list("path_mappings");
+    for (i, mapping) in config.path_mappings.iter().enumerate() {
+        println!("  - {}  # {}", mapping, origin_str(origins.get(i)));
+    }
+
+    Ok(())
+}
+
+/// List every config file `Config::load` looked at.
+fn cmd_config_path(config: &Config) {
+    for file in &config.provenance.files {
+        let status = match (file.loaded, &file.layer) {
+            (true, _) => "loaded",
+            (false, config::Layer::Project) => "not found here or in any parent directory",
+            (false, _) => "not found",
+        };
+        println!("{} ({}, {})", file.path.display(), file.layer, status);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Alias
+// ---------------------------------------------------------------------------
+
+/// List every alias with its raw members.
+fn cmd_alias_list(config: &Config) {
+    if config.aliases.is_empty() {
+        eprintln!("No aliases defined.");
+        return;
+    }
+    for (name, members) in sorted(&config.aliases) {
+        println!("{}: {}", name, members.join(" "));
+    }
+}
+
+/// Show an alias's members and what it expands to.
+fn cmd_alias_show(config: &Config, request: &str) -> Result<()> {
+    let expanded = config
+        .resolve_alias(request)?
+        .ok_or_else(|| anyhow::anyhow!("Unknown alias: {}", request))?;
+    let name = request.split_once('@').map_or(request, |(n, _)| n);
+
+    println!("Alias: {}", name);
+    if let Some(origin) = config.provenance.value(&format!("aliases.{}", name)) {
+        println!("Defined in: {}", origin);
+    }
+    println!("Members:");
+    for member in &config.aliases[name] {
+        println!("  - {}", member);
+    }
+    println!("Expands to:");
+    for req in &expanded {
+        println!("  - {}", req);
+    }
+
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Cache
+// ---------------------------------------------------------------------------
+
+/// Where the cache lives, and why.
+fn cache_dir_source(config: &Config) -> &'static str {
+    if std::env::var("ANVIL_CACHE_DIR").is_ok_and(|d| !d.is_empty()) {
+        "ANVIL_CACHE_DIR"
+    } else if config.cache_dir.is_some() {
+        "config"
+    } else {
+        "default"
+    }
+}
+
+/// Print the cache location, total size and each cached package path.
+fn cmd_cache_info(config: &Config) {
+    let Some(dir) = config.cache_root() else {
+        println!("Cache: disabled (no cache directory available)");
+        return;
+    };
+    let entries = cache::entries(&dir);
+    let total: u64 = entries.iter().map(|e| e.size).sum();
 
-    shell::spawn_shell(&shell_path, &ctx.environment)?;
+    println!("Cache: {} ({})", dir.display(), cache_dir_source(config));
+    println!(
+        "Size:  {} in {} entr{}",
+        format_size(total),
+        entries.len(),
+        if entries.len() == 1 { "y" } else { "ies" }
+    );
+    println!("Resolves: {} cached", cache::resolve_count(&dir));
+
+    if entries.is_empty() {
+        return;
+    }
+
+    let configured = config.all_package_paths();
+    println!();
+    for entry in &entries {
+        let current = configured.contains(&entry.path);
+        println!(
+            "{}{}",
+            entry.path.display(),
+            if current { "  [current config]" } else { "" }
+        );
+        let age = entry
+            .modified
+            .map(|m| format!("{} ago", cache::format_age(m)))
+            .unwrap_or_else(|| "unknown age".to_string());
+        println!(
+            "  {} families, {} files, {} packages; {}, written {}; fingerprint {:016x}",
+            entry.families,
+            entry.files,
+            entry.packages,
+            format_size(entry.size),
+            age,
+            entry.fingerprint
+        );
+    }
+}
+
+/// Delete every cached scan.
+fn cmd_cache_clear(config: &Config) -> Result<()> {
+    let Some(dir) = config.cache_root() else {
+        println!("Cache: disabled, nothing to clear");
+        return Ok(());
+    };
+    let removed = cache::clear(&dir)?;
+    println!("Removed {} cache file(s) from {}", removed, dir.display());
+    Ok(())
+}
+
+/// Scan package paths now so the next resolve reads from the cache.
+fn cmd_cache_warm(config: &Config, paths: &[String]) -> Result<()> {
+    let dir = config
+        .cache_root()
+        .ok_or_else(|| anyhow::anyhow!("No cache directory available; set ANVIL_CACHE_DIR"))?;
+
+    let paths: Vec<std::path::PathBuf> = if paths.is_empty() {
+        // Only the paths that exist; missing built-in defaults are normal.
+        config.all_package_paths()
+    } else {
+        // Lookups key the cache by the configured spelling of a path, so
+        // `./pkgs` or `pkgs/` warm the configured entry for the same
+        // directory, and anything else is warmed under its canonical path.
+        let configured: Vec<(std::path::PathBuf, std::path::PathBuf)> = config
+            .all_package_paths()
+            .into_iter()
+            .filter_map(|p| Some((p.canonicalize().ok()?, p)))
+            .collect();
+        paths
+            .iter()
+            .map(|p| {
+                let path = std::path::PathBuf::from(shellexpand::tilde(p).into_owned());
+                let Ok(canonical) = path.canonicalize() else {
+                    return path;
+                };
+                configured
+                    .iter()
+                    .find(|(c, _)| *c == canonical)
+                    .map(|(_, p)| p.clone())
+                    .unwrap_or(canonical)
+            })
+            .collect()
+    };
+
+    // Always rescan: warming is how you prime the cache after a big change.
+    for path in &paths {
+        if !path.exists() {
+            eprintln!("Skipping missing package path: {}", path.display());
+            continue;
+        }
+        let packages = cache::scan(Some(&dir), std::slice::from_ref(path), true)?;
+        println!("Warmed {} ({} packages)", path.display(), packages.len());
+    }
+    Ok(())
+}
 
+/// Write the repository index of a package path from scratch.
+fn cmd_index_build(path: &str) -> Result<()> {
+    let path = std::path::PathBuf::from(shellexpand::tilde(path).into_owned());
+    let summary = cache::write_index(&path, true)?;
+    println!(
+        "Indexed {} packages into {}",
+        summary.packages,
+        summary.file.display()
+    );
     Ok(())
 }
 
+/// Format a byte count for humans.
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Iterate a map in key order so output is stable.
+fn sorted<V>(map: &std::collections::HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
 // ---------------------------------------------------------------------------
 // Init
 // ---------------------------------------------------------------------------
@@ -631,7 +1749,7 @@ fn cmd_wrap(
     wrapper_shell: &str,
     refresh: bool,
 ) -> Result<()> {
-    let resolver = Resolver::new(config, refresh)?;
+    let resolver = Resolver::new_lazy(config, refresh)?;
     let resolved = resolver.resolve(packages)?;
     let commands = resolved.commands();
 
@@ -649,7 +1767,7 @@ fn cmd_wrap(
     let pkg_str = pkg_args.join(" ");
 
     let mut count = 0;
-    for (alias, _target) in &commands {
+    for alias in commands.keys() {
         let script = generate_wrapper(wrapper_shell, &pkg_str, alias);
         let out_path = dir_path.join(alias);
         std::fs::write(&out_path, script)?;
@@ -675,7 +1793,7 @@ fn cmd_wrap(
 }
 
 fn generate_wrapper(shell: &str, packages: &str, command: &str) -> String {
-    match shell {
+    match shell::shell_name(shell) {
         "fish" => format!(
             "#!/usr/bin/env fish\nexec anvil run {} -- {} $argv\n",
             packages, command
@@ -684,6 +1802,24 @@ fn generate_wrapper(shell: &str, packages: &str, command: &str) -> String {
             "#!/usr/bin/env pwsh\nanvil run {} -- {} @args\n",
             packages, command
         ),
+        // `-f` skips ~/.cshrc; `$argv:q` keeps each argument one word.
+        "tcsh" | "csh" => format!(
+            "#!/bin/csh -f\nexec anvil run {} -- {} $argv:q\n",
+            packages, command
+        ),
+        // `--wrapped` passes flags through to the command untouched.
+        "nu" => format!(
+            "#!/usr/bin/env nu\ndef --wrapped main [...args] {{\n    ^anvil run {} -- {} ...$args\n}}\n",
+            packages, command
+        ),
+        "elvish" => format!(
+            "#!/usr/bin/env elvish\nanvil run {} -- {} $@args\n",
+            packages, command
+        ),
+        "xonsh" => format!(
+            "#!/usr/bin/env xonsh\nexec anvil run {} -- {} @($ARGS[1:])\n",
+            packages, command
+        ),
         _ => format!(
             "#!/usr/bin/env bash\nexec anvil run {} -- {} \"$@\"\n",
             packages, command
@@ -763,6 +1899,18 @@ fn cmd_publish(target: &str, source: Option<&str>, flat: bool) -> Result<()> {
         );
     }
 
+    // Keep an existing repository index current; only the family that was
+    // just published gets parsed.
+    if target_path.join(cache::INDEX_FILE).exists() {
+        let summary = cache::write_index(target_path, false)?;
+        println!(
+            "Updated index {} ({} entr{} re-read)",
+            summary.file.display(),
+            summary.reread,
+            if summary.reread == 1 { "y" } else { "ies" }
+        );
+    }
+
     Ok(())
 }
 
@@ -7,17 +7,89 @@ use anyhow::{Context, Result};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-/// Platform-native path-list separator, exposed in yaml as `${PATHSEP}`.
-#[cfg(target_os = "windows")]
-pub const PATHSEP: &str = ";";
-#[cfg(not(target_os = "windows"))]
-pub const PATHSEP: &str = ":";
-
-/// Platform-native executable suffix, exposed in yaml as `${EXE_SUFFIX}`.
-#[cfg(target_os = "windows")]
-pub const EXE_SUFFIX: &str = ".exe";
-#[cfg(not(target_os = "windows"))]
-pub const EXE_SUFFIX: &str = "";
+/// Operating systems a package can be resolved for.
+pub const PLATFORMS: &[&str] = &["linux", "macos", "windows"];
+
+/// A target to resolve packages for: operating system plus CPU
+/// architecture.  Decides which `variants:` apply and what the
+/// `${PATHSEP}` / `${EXE_SUFFIX}` builtins expand to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Platform {
+    /// linux, macos or windows
+    pub os: String,
+    /// CPU architecture as Rust names it (x86_64, aarch64, ...)
+    pub arch: String,
+}
+
+impl Platform {
+    /// The platform anvil is running on.
+    pub fn current() -> Self {
+        Platform {
+            os: crate::context::SavedContext::current_platform().to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    /// Parse `os` or `os-arch` (e.g. `windows`, `linux-aarch64`).  The
+    /// architecture defaults to the host's.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (os, arch) = match spec.split_once('-') {
+            Some((os, arch)) => (os, arch),
+            None => (spec, std::env::consts::ARCH),
+        };
+        if !PLATFORMS.contains(&os) {
+            anyhow::bail!(
+                "Unknown platform '{}' (expected one of: {})",
+                os,
+                PLATFORMS.join(", ")
+            );
+        }
+        if arch.is_empty() {
+            anyhow::bail!("Missing architecture in platform '{}'", spec);
+        }
+        Ok(Platform {
+            os: os.to_string(),
+            arch: arch.to_string(),
+        })
+    }
+
+    /// Path-list separator, exposed in yaml as `${PATHSEP}`.
+    pub fn pathsep(&self) -> &'static str {
+        pathsep_for(&self.os)
+    }
+
+    /// Executable suffix, exposed in yaml as `${EXE_SUFFIX}`.
+    pub fn exe_suffix(&self) -> &'static str {
+        if self.os == "windows" {
+            ".exe"
+        } else {
+            ""
+        }
+    }
+}
+
+/// Path-list separator of an operating system (linux, macos, windows).
+pub fn pathsep_for(os: &str) -> &'static str {
+    if os == "windows" {
+        ";"
+    } else {
+        ":"
+    }
+}
+
+/// Whether a path-list segment is a path: absolute, home-relative or
+/// dot-relative Unix paths, and Windows drive or UNC paths.
+pub fn looks_like_path(segment: &str) -> bool {
+    let bytes = segment.as_bytes();
+    segment.starts_with(['/', '~', '.', '\\'])
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.os, self.arch)
+    }
+}
 
 /// A package definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,12 +122,20 @@ pub struct Package {
     /// Path to the package root (set after loading, omitted from package.yaml)
     #[serde(default)]
     pub root: PathBuf,
+
+    /// The YAML file this definition was read from (set after loading)
+    #[serde(default)]
+    pub definition: PathBuf,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageVariant {
     /// Platform filter (linux, windows, macos)
     pub platform: Option<String>,
+
+    /// Architecture filter (x86_64, aarch64, ...)
+    #[serde(default)]
+    pub arch: Option<String>,
     
     /// Additional requires for this variant
     #[serde(default)]
@@ -95,9 +175,7 @@ impl Package {
             .map(|p| p.to_path_buf())
             .or_else(|| file_path.parent().map(|p| p.to_path_buf()))
             .unwrap_or_default();
-
-        // Apply variant for current platform
-        package.apply_current_variant();
+        package.definition = file_path.to_path_buf();
 
         Ok(package)
     }
@@ -107,23 +185,21 @@ impl Package {
         format!("{}-{}", self.name, self.version)
     }
     
-    /// Apply the variant matching the current platform
-    fn apply_current_variant(&mut self) {
-        let current_platform = if cfg!(target_os = "linux") {
-            "linux"
-        } else if cfg!(target_os = "windows") {
-            "windows"
-        } else if cfg!(target_os = "macos") {
-            "macos"
-        } else {
-            return;
-        };
-        
+    /// Merge the variants matching `platform` into the package's requires
+    /// and environment.  A variant matches when every filter it sets
+    /// (`platform`, `arch`) does.  The resolver does this when a package is
+    /// admitted, so loaded (and cached) packages stay neutral.
+    pub fn apply_variant(&mut self, platform: &Platform) {
         for variant in &self.variants {
-            if variant.platform.as_deref() == Some(current_platform) {
+            if variant.platform.is_none() && variant.arch.is_none() {
+                continue;
+            }
+            let os_matches = variant.platform.as_deref().is_none_or(|os| os == platform.os);
+            let arch_matches = variant.arch.as_deref().is_none_or(|arch| arch == platform.arch);
+            if os_matches && arch_matches {
                 // Merge variant requires
                 self.requires.extend(variant.requires.clone());
-                
+
                 // Merge variant environment
                 for (key, value) in &variant.environment {
                     self.environment.insert(key.clone(), value.clone());
@@ -131,9 +207,26 @@ impl Package {
             }
         }
     }
-    
+
     /// Expand environment variables and tilde in a value
     pub fn expand_env_value(&self, value: &str, env: &HashMap<String, String>) -> String {
+        self.expand_env_value_for(value, env, &Platform::current())
+    }
+
+    /// Like `expand_env_value`, with the platform builtins following
+    /// `platform` instead of the host.
+    pub fn expand_env_value_for(
+        &self,
+        value: &str,
+        env: &HashMap<String, String>,
+        platform: &Platform,
+    ) -> String {
+        expand_vars(&self.expand_builtins_for(value, platform), env)
+    }
+
+    /// Replace the package builtins in a value, leaving `${VAR}` references
+    /// to other variables and `~/` for later.
+    pub fn expand_builtins_for(&self, value: &str, platform: &Platform) -> String {
         let mut result = value.to_string();
 
         // Replace ${PACKAGE_ROOT} with actual path
@@ -147,39 +240,11 @@ impl Package {
 
         // Platform-aware builtins so a single yaml line can compose path
         // lists or binary names without a `variants:` fork per platform.
-        result = result.replace("${PATHSEP}", PATHSEP);
-        result = result.replace("${EXE_SUFFIX}", EXE_SUFFIX);
-
-        // Replace other ${VAR} references
-        for (key, val) in env {
-            result = result.replace(&format!("${{{}}}", key), val);
-        }
-
-        // Replace remaining ${VAR} with current environment
-        let re = regex::Regex::new(r"\$\{([^}]+)\}").unwrap();
-        result = re.replace_all(&result, |caps: &regex::Captures| {
-            let var = &caps[1];
-            std::env::var(var).unwrap_or_default()
-        }).to_string();
-
-        // Expand `~/` everywhere it appears at a segment boundary
-        // (start-of-value, or after `:` / `;`).  Path-list values like
-        // `~/USD/bin;~/USD/lib` need every occurrence expanded, not just
-        // the first.  `dirs::home_dir()` resolves via `USERPROFILE` on
-        // Windows when `HOME` is unset (PowerShell sessions).
-        if let Some(home) = dirs::home_dir() {
-            let home_str = home.to_string_lossy();
-            let tilde_re = regex::Regex::new(r"(^|[:;])~/").unwrap();
-            result = tilde_re
-                .replace_all(&result, |caps: &regex::Captures| {
-                    format!("{}{}/", &caps[1], home_str)
-                })
-                .to_string();
-        }
+        result = result.replace("${PATHSEP}", platform.pathsep());
+        result = result.replace("${EXE_SUFFIX}", platform.exe_suffix());
 
         result
     }
-    
     /// Get resolved environment for this package
     pub fn resolved_environment(&self, base_env: &HashMap<String, String>) -> HashMap<String, String> {
         let mut env = base_env.clone();
@@ -193,6 +258,40 @@ impl Package {
     }
 }
 
+/// Expand `${VAR}` references from `env`, then `~/`.
+pub fn expand_vars(value: &str, env: &HashMap<String, String>) -> String {
+    let mut result = value.to_string();
+
+    // Replace other ${VAR} references
+    for (key, val) in env {
+        result = result.replace(&format!("${{{}}}", key), val);
+    }
+
+    // Remaining ${VAR} references aren't set in `env` (which already
+    // holds the inherited environment), so they expand to empty.  Falling
+    // back to the process environment would leak variables that
+    // `inherit.deny` or `--pure` removed.
+    let re = regex::Regex::new(r"\$\{([^}]+)\}").unwrap();
+    result = re.replace_all(&result, "").to_string();
+
+    // Expand `~/` everywhere it appears at a segment boundary
+    // (start-of-value, or after `:` / `;`).  Path-list values like
+    // `~/USD/bin;~/USD/lib` need every occurrence expanded, not just
+    // the first.  `dirs::home_dir()` resolves via `USERPROFILE` on
+    // Windows when `HOME` is unset (PowerShell sessions).
+    if let Some(home) = dirs::home_dir() {
+        let home_str = home.to_string_lossy();
+        let tilde_re = regex::Regex::new(r"(^|[:;])~/").unwrap();
+        result = tilde_re
+            .replace_all(&result, |caps: &regex::Captures| {
+                format!("{}{}/", &caps[1], home_str)
+            })
+            .to_string();
+    }
+
+    result
+}
+
 /// Tokenize a command-alias value into `[program, args...]`.
 ///
 /// If the whole value — after tilde expansion — names an existing file, it's
@@ -225,6 +324,8 @@ pub enum VersionConstraint {
     Exact(String),
     /// Minimum version (>=)
     Minimum(String),
+    /// Upper bound, exclusive (<)
+    Below(String),
     /// Range (inclusive)
     Range(String, String),
     /// Multiple options (|)
@@ -245,17 +346,19 @@ impl PackageRequest {
             let name = &s[..idx];
             let version_part = &s[idx + 1..];
 
-            // Only treat the suffix as a version when it begins with a digit.
-            // This prevents "studio-blender-tools" from being parsed as
-            // name="studio-blender" version="tools".
+            // Only treat the suffix as a version when it begins with a digit
+            // (or `<` for an upper bound).  This prevents "studio-blender-tools"
+            // from being parsed as name="studio-blender" version="tools".
             let starts_with_digit = version_part
                 .chars()
                 .next()
-                .map_or(false, |c| c.is_ascii_digit());
+                .is_some_and(|c| c.is_ascii_digit() || c == '<');
 
             if starts_with_digit {
                 // Parse version constraint
-                let constraint = if version_part.ends_with('+') {
+                let constraint = if let Some(max) = version_part.strip_prefix('<') {
+                    VersionConstraint::Below(max.to_string())
+                } else if version_part.ends_with('+') {
                     VersionConstraint::Minimum(version_part.trim_end_matches('+').to_string())
                 } else if version_part.contains("..") {
                     let parts: Vec<&str> = version_part.split("..").collect();
@@ -293,6 +396,9 @@ impl PackageRequest {
             VersionConstraint::Minimum(min) => {
                 version_compare(version, min) >= std::cmp::Ordering::Equal
             }
+            VersionConstraint::Below(max) => {
+                version_compare(version, max) == std::cmp::Ordering::Less
+            }
             VersionConstraint::Range(min, max) => {
                 version_compare(version, min) >= std::cmp::Ordering::Equal
                     && version_compare(version, max) <= std::cmp::Ordering::Equal
@@ -341,6 +447,13 @@ mod tests {
         assert!(matches!(req.version_constraint, VersionConstraint::Minimum(v) if v == "2024"));
     }
 
+    #[test]
+    fn parse_below_version() {
+        let req = PackageRequest::parse("maya-<2024").unwrap();
+        assert_eq!(req.name, "maya");
+        assert!(matches!(req.version_constraint, VersionConstraint::Below(v) if v == "2024"));
+    }
+
     #[test]
     fn parse_range_version() {
         let req = PackageRequest::parse("maya-2024..2025").unwrap();
@@ -407,6 +520,14 @@ mod tests {
         assert!(!req.matches("2023"));
     }
 
+    #[test]
+    fn match_below() {
+        let req = PackageRequest::parse("maya-<2024").unwrap();
+        assert!(req.matches("2023"));
+        assert!(!req.matches("2024"));
+        assert!(!req.matches("2025"));
+    }
+
     #[test]
     fn match_range() {
         let req = PackageRequest::parse("maya-2024..2025").unwrap();
@@ -454,6 +575,7 @@ mod tests {
             commands: HashMap::new(),
             variants: vec![],
             root: PathBuf::from("/opt/test/1.0"),
+            definition: PathBuf::new(),
         };
         let env = HashMap::new();
         assert_eq!(
@@ -473,6 +595,7 @@ mod tests {
             commands: HashMap::new(),
             variants: vec![],
             root: PathBuf::from("/opt/maya"),
+            definition: PathBuf::new(),
         };
         let env = HashMap::new();
         assert_eq!(pkg.expand_env_value("${NAME}-${VERSION}", &env), "maya-2024");
@@ -489,6 +612,7 @@ mod tests {
             commands: HashMap::new(),
             variants: vec![],
             root: PathBuf::from("/tmp"),
+            definition: PathBuf::new(),
         };
         let env = HashMap::new();
         let expected = if cfg!(target_os = "windows") {
@@ -513,6 +637,7 @@ mod tests {
             commands: HashMap::new(),
             variants: vec![],
             root: PathBuf::from("/tmp"),
+            definition: PathBuf::new(),
         };
         let env = HashMap::new();
         let expected = if cfg!(target_os = "windows") {
@@ -537,6 +662,7 @@ mod tests {
             commands: HashMap::new(),
             variants: vec![],
             root: PathBuf::from("/tmp"),
+            definition: PathBuf::new(),
         };
         let env = HashMap::new();
         let home = dirs::home_dir().expect("test needs a HOME");
@@ -574,6 +700,7 @@ mod tests {
             commands: HashMap::new(),
             variants: vec![],
             root: PathBuf::from("/tmp"),
+            definition: PathBuf::new(),
         };
         let env = HashMap::new();
         // No `~/` at start or after `:` / `;`, so nothing should change.
@@ -591,6 +718,7 @@ mod tests {
             commands: HashMap::new(),
             variants: vec![],
             root: PathBuf::from("/tmp"),
+            definition: PathBuf::new(),
         };
         let mut env = HashMap::new();
         env.insert("HFS".into(), "/opt/houdini".into());
@@ -599,4 +727,93 @@ mod tests {
             "/opt/houdini/bin:/opt/houdini/python"
         );
     }
+
+    #[test]
+    fn variant_applied_per_platform() {
+        let yaml = r#"
+name: usd
+version: "24.08"
+variants:
+  - platform: windows
+    requires: [msvc-2022]
+    environment:
+      USD_PLATFORM: win64
+"#;
+        let loaded: Package = serde_yaml::from_str(yaml).unwrap();
+        assert!(loaded.requires.is_empty());
+
+        let mut linux = loaded.clone();
+        linux.apply_variant(&Platform::parse("linux").unwrap());
+        assert!(linux.requires.is_empty());
+        assert!(!linux.environment.contains_key("USD_PLATFORM"));
+
+        let mut windows = loaded;
+        windows.apply_variant(&Platform::parse("windows").unwrap());
+        assert_eq!(windows.requires, vec!["msvc-2022".to_string()]);
+        assert_eq!(windows.environment["USD_PLATFORM"], "win64");
+    }
+
+    #[test]
+    fn builtins_follow_target_platform() {
+        let pkg = Package {
+            name: "blender".into(),
+            version: "4.1".into(),
+            description: None,
+            requires: vec![],
+            environment: IndexMap::new(),
+            commands: HashMap::new(),
+            variants: vec![],
+            root: PathBuf::from("/tmp"),
+            definition: PathBuf::new(),
+        };
+        let env = HashMap::new();
+        let windows = Platform::parse("windows").unwrap();
+        let linux = Platform::parse("linux").unwrap();
+        assert_eq!(
+            pkg.expand_env_value_for("/a${PATHSEP}blender${EXE_SUFFIX}", &env, &windows),
+            "/a;blender.exe"
+        );
+        assert_eq!(
+            pkg.expand_env_value_for("/a${PATHSEP}blender${EXE_SUFFIX}", &env, &linux),
+            "/a:blender"
+        );
+    }
+
+    #[test]
+    fn parse_platform() {
+        let p = Platform::parse("linux-aarch64").unwrap();
+        assert_eq!((p.os.as_str(), p.arch.as_str()), ("linux", "aarch64"));
+        assert_eq!(Platform::parse("windows").unwrap().arch, std::env::consts::ARCH);
+        assert!(Platform::parse("beos").is_err());
+        assert!(Platform::parse("linux-").is_err());
+    }
+
+    #[test]
+    fn variant_filtered_by_arch() {
+        let yaml = r#"
+name: houdini
+version: "20.5"
+variants:
+  - arch: aarch64
+    environment:
+      HOUDINI_ARCH: arm
+  - platform: linux
+    arch: x86_64
+    environment:
+      HOUDINI_ARCH: x64
+"#;
+        let loaded: Package = serde_yaml::from_str(yaml).unwrap();
+
+        let mut arm = loaded.clone();
+        arm.apply_variant(&Platform::parse("macos-aarch64").unwrap());
+        assert_eq!(arm.environment["HOUDINI_ARCH"], "arm");
+
+        let mut x64 = loaded.clone();
+        x64.apply_variant(&Platform::parse("linux-x86_64").unwrap());
+        assert_eq!(x64.environment["HOUDINI_ARCH"], "x64");
+
+        let mut other = loaded;
+        other.apply_variant(&Platform::parse("windows-x86_64").unwrap());
+        assert!(!other.environment.contains_key("HOUDINI_ARCH"));
+    }
 }
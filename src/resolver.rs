@@ -1,19 +1,24 @@
 //! Package resolution and dependency management
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::{Context, Result};
 use tracing::{debug, info, warn};
 
 use crate::cache;
 use crate::config::Config;
-use crate::context::Lockfile;
-use crate::package::{tokenize_command, Package, PackageRequest};
+use crate::context::{EnvOp, Lockfile};
+use crate::package::{tokenize_command, Package, PackageRequest, Platform};
 
 /// Resolved set of packages
 #[derive(Debug)]
 pub struct ResolvedPackages {
     packages: Vec<Package>,
+    /// Inherited variables the package environments are layered on.
+    base: HashMap<String, String>,
+    /// Platform the packages were resolved for.
+    platform: Platform,
 }
 
 impl ResolvedPackages {
@@ -22,7 +27,7 @@ impl ResolvedPackages {
     /// Emits warnings when a variable explicitly set by one package is
     /// overridden (not appended to) by a later package.
     pub fn environment(&self) -> HashMap<String, String> {
-        let mut env: HashMap<String, String> = std::env::vars().collect();
+        let mut env = self.base.clone();
 
         // Track which package explicitly set each key so we can detect overrides.
         let mut owners: HashMap<String, String> = HashMap::new();
@@ -41,15 +46,61 @@ impl ResolvedPackages {
                     }
                 }
                 owners.insert(key.clone(), package.id());
-            }
 
-            let pkg_env = package.resolved_environment(&env);
-            env.extend(pkg_env);
+                // Same as `resolved_environment`, without copying the whole
+                // environment once per package.
+                let expanded = package.expand_env_value_for(raw_value, &env, &self.platform);
+                env.insert(key.clone(), expanded);
+            }
         }
 
         env
     }
 
+    /// The package environment entries as operations to replay on any
+    /// base environment, in the order `environment` applies them.
+    pub fn operations(&self) -> Vec<EnvOp> {
+        self.packages
+            .iter()
+            .flat_map(|package| {
+                package.environment.iter().map(|(key, raw_value)| EnvOp {
+                    name: key.clone(),
+                    value: package.expand_builtins_for(raw_value, &self.platform),
+                    package: package.id(),
+                })
+            })
+            .collect()
+    }
+
+    /// The variables the packages set, with their final values: the
+    /// environment without what was only inherited.
+    pub fn variables(&self) -> HashMap<String, String> {
+        let mut env = self.environment();
+        env.retain(|key, _| {
+            self.packages
+                .iter()
+                .any(|package| package.environment.contains_key(key))
+        });
+        env
+    }
+
+    /// The merged command aliases with only the package builtins expanded,
+    /// for a saved context to finish on the machine that loads it.
+    pub fn command_templates(&self) -> BTreeMap<String, String> {
+        let mut commands = BTreeMap::new();
+        for package in &self.packages {
+            for (alias, target) in &package.commands {
+                commands.insert(alias.clone(), package.expand_builtins_for(target, &self.platform));
+            }
+        }
+        commands
+    }
+
+    /// The inherited environment the packages are layered on.
+    pub fn base(&self) -> &HashMap<String, String> {
+        &self.base
+    }
+
     /// Get list of resolved packages
     pub fn packages(&self) -> &[Package] {
         &self.packages
@@ -62,7 +113,7 @@ impl ResolvedPackages {
 
         for package in &self.packages {
             for (alias, target) in &package.commands {
-                let expanded = package.expand_env_value(target, &env);
+                let expanded = package.expand_env_value_for(target, &env, &self.platform);
                 commands.insert(alias.clone(), expanded);
             }
         }
@@ -71,162 +122,372 @@ impl ResolvedPackages {
     }
 }
 
+/// A package version removed by the config filters.
+#[derive(Debug, Clone)]
+pub struct HiddenPackage {
+    pub name: String,
+    pub version: String,
+    /// The filter rule that hid it.
+    pub rule: String,
+}
+
 /// Package resolver
 pub struct Resolver {
     config: Config,
     /// Cache of loaded packages: name -> version -> Package
-    package_cache: HashMap<String, HashMap<String, Package>>,
+    package_cache: RefCell<HashMap<String, HashMap<String, Package>>>,
     /// Version pins from a lockfile (empty when unlocked).
     pins: HashMap<String, String>,
+    /// Package versions removed by `filters:`.
+    hidden: RefCell<Vec<HiddenPackage>>,
+    /// Package id -> keys of the config `overrides:` applied to it.
+    overridden: RefCell<HashMap<String, Vec<String>>>,
+    /// Lazy mode: families listed on disk but not parsed yet.  `None` once
+    /// every package is loaded.
+    pending: RefCell<Option<HashMap<String, Vec<cache::Source>>>>,
+    /// Lazy mode: families parsed so far, with the sources they came from.
+    consulted: RefCell<BTreeMap<String, Vec<cache::Source>>>,
+    /// Packages of configured paths that are unreachable, taken from their
+    /// last cached scan.
+    offline: HashMap<String, HashMap<String, Package>>,
+    /// Platform whose package variants and builtins apply.
+    platform: Platform,
+    refresh: bool,
 }
 
 impl Resolver {
     /// Create a new resolver, automatically loading `anvil.lock` if present.
     /// When `refresh` is true, the package scan cache is bypassed.
     pub fn new(config: &Config, refresh: bool) -> Result<Self> {
-        let pins = if let Some(lock_path) = Lockfile::find() {
-            let lockfile = Lockfile::load(&lock_path)?;
-            info!("Using lockfile: {:?}", lock_path);
-            lockfile.pins
-        } else {
-            HashMap::new()
-        };
-
-        let mut resolver = Resolver {
-            config: config.clone(),
-            package_cache: HashMap::new(),
-            pins,
-        };
-        resolver.load_packages(refresh)?;
+        let platform = Platform::current();
+        let resolver = Self::empty(config, Self::lockfile_pins(&platform.os)?, &platform, refresh)?;
+        resolver.load_packages()?;
         Ok(resolver)
     }
 
     /// Create a resolver that ignores any existing lockfile.
     pub fn new_unlocked(config: &Config, refresh: bool) -> Result<Self> {
-        let mut resolver = Resolver {
+        Self::with_pins(config, HashMap::new(), &Platform::current(), refresh)
+    }
+
+    /// Create a resolver with explicit version pins instead of the ones in
+    /// `anvil.lock`, applying the package variants of `platform`.
+    pub fn with_pins(
+        config: &Config,
+        pins: HashMap<String, String>,
+        platform: &Platform,
+        refresh: bool,
+    ) -> Result<Self> {
+        let resolver = Self::empty(config, pins, platform, refresh)?;
+        resolver.load_packages()?;
+        Ok(resolver)
+    }
+
+    /// Create a resolver that only lists family directories up front and
+    /// parses the packages a resolve actually reaches.  Meant for `env`,
+    /// `run` and friends on large shared repositories; `list` and
+    /// `validate` need the full scan of `new`.
+    ///
+    /// Lazy lookup assumes a family directory (or flat file prefix) is
+    /// named after the package.  A name it can't find that way triggers a
+    /// full scan before giving up.
+    pub fn new_lazy(config: &Config, refresh: bool) -> Result<Self> {
+        Self::new_lazy_for(config, &Platform::current(), refresh)
+    }
+
+    /// A lazy resolver for another platform: its variants, `anvil.lock`
+    /// pin set and `${PATHSEP}` / `${EXE_SUFFIX}` apply instead of the
+    /// host's.
+    pub fn new_lazy_for(config: &Config, platform: &Platform, refresh: bool) -> Result<Self> {
+        let resolver = Self::empty(config, Self::lockfile_pins(&platform.os)?, platform, refresh)?;
+        let families = cache::list_families(&config.all_package_paths())?;
+        debug!("Lazy resolver: {} families listed", families.len());
+        *resolver.pending.borrow_mut() = Some(families);
+        resolver.admit(resolver.offline.clone());
+        Ok(resolver)
+    }
+
+    fn empty(
+        config: &Config,
+        pins: HashMap<String, String>,
+        platform: &Platform,
+        refresh: bool,
+    ) -> Result<Self> {
+        Ok(Resolver {
             config: config.clone(),
-            package_cache: HashMap::new(),
-            pins: HashMap::new(),
+            package_cache: RefCell::new(HashMap::new()),
+            pins,
+            hidden: RefCell::new(Vec::new()),
+            overridden: RefCell::new(HashMap::new()),
+            pending: RefCell::new(None),
+            consulted: RefCell::new(BTreeMap::new()),
+            offline: Self::offline_packages(config)?,
+            platform: platform.clone(),
+            refresh,
+        })
+    }
+
+    /// Fall back to the last cached scan of every configured package path
+    /// that can't be reached, warning about each.  With
+    /// `require_package_paths: true` an unreachable path is an error.
+    fn offline_packages(config: &Config) -> Result<HashMap<String, HashMap<String, Package>>> {
+        let mut packages: HashMap<String, HashMap<String, Package>> = HashMap::new();
+        let missing = config.missing_package_paths();
+        if missing.is_empty() {
+            return Ok(packages);
+        }
+
+        if config.require_package_paths == Some(true) {
+            let listed: Vec<String> = missing.iter().map(|p| p.display().to_string()).collect();
+            anyhow::bail!(
+                "Package path unreachable: {} (require_package_paths is set)",
+                listed.join(", ")
+            );
+        }
+
+        let cache_dir = config.cache_root();
+        for path in missing {
+            let cached = cache_dir
+                .as_deref()
+                .and_then(|dir| cache::load_offline(dir, &path));
+            let Some((cached, written)) = cached else {
+                warn!(
+                    "Package path {} is unreachable and has no cached scan; skipping it",
+                    path.display()
+                );
+                continue;
+            };
+            warn!(
+                "Package path {} is unreachable; using its cached scan from {} ({} packages)",
+                path.display(),
+                written.map_or("an unknown time".to_string(), |w| format!("{} ago", cache::format_age(w))),
+                cached.len()
+            );
+            for pkg in cached {
+                packages
+                    .entry(pkg.name.clone())
+                    .or_default()
+                    .insert(pkg.version.clone(), pkg);
+            }
+        }
+        Ok(packages)
+    }
+
+    /// Pins for `platform` from `anvil.lock`, if one is found.
+    fn lockfile_pins(platform: &str) -> Result<HashMap<String, String>> {
+        let Some(lock_path) = Lockfile::find() else {
+            return Ok(HashMap::new());
         };
-        resolver.load_packages(refresh)?;
-        Ok(resolver)
+        let lockfile = Lockfile::load(&lock_path)?;
+        match lockfile.pin_set(platform) {
+            Some(set) => {
+                info!("Using lockfile: {:?}", lock_path);
+                Ok(set.pins.clone())
+            }
+            None => {
+                warn!("{:?} has no pins for {}; resolving unpinned", lock_path, platform);
+                Ok(HashMap::new())
+            }
+        }
     }
 
-    /// Load packages: try the cache first (unless `refresh`), fall back to a full scan.
-    fn load_packages(&mut self, refresh: bool) -> Result<()> {
+    /// Load every package through the incremental scan cache.  When
+    /// `refresh` is set every package file is re-read.
+    fn load_packages(&self) -> Result<()> {
         let paths = self.config.all_package_paths();
-        // Include config state in the cache key so different configs
-        // don't share a cache (e.g. different filters or package paths).
-        let salt = format!("{:?}{:?}", self.config.package_paths, self.config.filters);
-
-        // Try cache
-        if !refresh {
-            if let Some(cached) = cache::load(&paths, &salt) {
-                self.package_cache = cached;
-                self.apply_filters();
-                info!("Loaded {} packages (cached)", self.package_cache.len());
-                return Ok(());
-            }
-        } else {
+
+        if self.refresh {
             info!("Bypassing package scan cache (--refresh)");
         }
 
-        // Full scan
-        self.scan_packages()?;
-        self.apply_filters();
-
-        // Save to cache (best-effort, before filter so cache stores everything)
-        if let Err(e) = cache::save(&paths, &salt, &self.package_cache) {
-            debug!("Failed to save cache: {}", e);
+        // The cache stores everything; filters and overrides apply on top.
+        let cache_dir = self.config.cache_root();
+        let loaded = cache::scan(
+            cache_dir.as_deref(),
+            &paths,
+            self.refresh,
+        )?;
+        info!("Loaded {} packages", loaded.len());
+
+        // Reachable paths win over cached copies of unreachable ones.
+        let mut loaded = loaded;
+        for (name, versions) in &self.offline {
+            let known = loaded.entry(name.clone()).or_default();
+            for (version, pkg) in versions {
+                known.entry(version.clone()).or_insert_with(|| pkg.clone());
+            }
         }
 
+        // Everything is admitted again, so forget what the lazily loaded
+        // families already recorded.
+        *self.package_cache.borrow_mut() = HashMap::new();
+        self.hidden.borrow_mut().clear();
+        self.overridden.borrow_mut().clear();
+        self.admit(loaded);
+        *self.pending.borrow_mut() = None;
+
         Ok(())
     }
 
-    /// Apply include/exclude filters from config.
-    fn apply_filters(&mut self) {
-        let filters = &self.config.filters;
-        if filters.include.is_empty() && filters.exclude.is_empty() {
-            return;
+    /// In lazy mode, make sure the family `name` is loaded.  Falls back to
+    /// a full scan when the directory listing doesn't know the name.
+    fn ensure_loaded(&self, name: &str) -> Result<()> {
+        let sources = match self.pending.borrow_mut().as_mut() {
+            None => return Ok(()),
+            Some(pending) => pending.remove(name),
+        };
+
+        match sources {
+            Some(sources) => {
+                self.consulted
+                    .borrow_mut()
+                    .insert(name.to_string(), sources.clone());
+                let packages = cache::load_sources(
+                    self.config.cache_root().as_deref(),
+                    &sources,
+                    self.refresh,
+                );
+                let mut loaded: HashMap<String, HashMap<String, Package>> = HashMap::new();
+                for pkg in packages {
+                    loaded
+                        .entry(pkg.name.clone())
+                        .or_default()
+                        .insert(pkg.version.clone(), pkg);
+                }
+                debug!("Lazily loaded family {}", name);
+                self.admit(loaded);
+            }
+            None if !self.package_cache.borrow().contains_key(name) => {
+                info!("{} not found by directory listing, scanning all packages", name);
+                self.load_packages()?;
+            }
+            None => {}
         }
+        Ok(())
+    }
 
-        self.package_cache
-            .retain(|name, _| filters.allows(name));
+    /// Apply platform variants, path mappings, filters and overrides to
+    /// freshly loaded packages and add them to the package cache.
+    fn admit(&self, mut loaded: HashMap<String, HashMap<String, Package>>) {
+        let host = Platform::current();
+        for pkg in loaded.values_mut().flat_map(|versions| versions.values_mut()) {
+            pkg.apply_variant(&self.platform);
+            if self.platform.os != host.os {
+                self.remap_package(pkg, &host.os);
+            }
+        }
+        self.apply_filters(&mut loaded);
+        self.apply_overrides(&mut loaded);
+        let mut cache = self.package_cache.borrow_mut();
+        for (name, versions) in loaded {
+            cache.entry(name).or_default().extend(versions);
+        }
     }
 
-    /// Scan package paths and load all packages.
-    fn scan_packages(&mut self) -> Result<()> {
-        for base_path in self.config.all_package_paths() {
-            debug!("Scanning packages in {:?}", base_path);
+    /// Translate a package's root and the paths in its environment and
+    /// commands from the host's mounts to the target platform's, following
+    /// `path_mappings:`.
+    fn remap_package(&self, pkg: &mut Package, host: &str) {
+        let to = self.platform.os.as_str();
+        let root = pkg.root.to_string_lossy().into_owned();
+        if let Some(root) = self.config.remap_path(&root, host, to) {
+            pkg.root = std::path::PathBuf::from(root);
+        }
+        for value in pkg.environment.values_mut() {
+            *value = self.config.remap_value(value, host, to);
+        }
+        for target in pkg.commands.values_mut() {
+            *target = self.config.remap_value(target, host, to);
+        }
+    }
 
-            if !base_path.exists() {
+    /// Patch loaded packages with the config `overrides:` section.  Applied
+    /// after caching so the cache always holds the packages as published.
+    fn apply_overrides(&self, packages: &mut HashMap<String, HashMap<String, Package>>) {
+        let mut overridden = self.overridden.borrow_mut();
+        for (key, ovr) in &self.config.overrides {
+            let request = match PackageRequest::parse(key) {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!("Ignoring override {:?}: {}", key, e);
+                    continue;
+                }
+            };
+            let Some(versions) = packages.get_mut(&request.name) else {
                 continue;
+            };
+            for pkg in versions.values_mut() {
+                if !request.matches(&pkg.version) {
+                    continue;
+                }
+                debug!("Applying override {:?} to {}", key, pkg.id());
+                for (k, v) in &ovr.environment {
+                    pkg.environment.insert(k.clone(), v.clone());
+                }
+                pkg.requires.extend(ovr.requires.iter().cloned());
+                pkg.commands
+                    .extend(ovr.commands.iter().map(|(k, v)| (k.clone(), v.clone())));
+                overridden.entry(pkg.id()).or_default().push(key.clone());
             }
+        }
+    }
 
-            for entry in std::fs::read_dir(&base_path)? {
-                let entry = entry?;
-                let path = entry.path();
-
-                if path.is_file() {
-                    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                    if ext == "yaml" || ext == "yml" {
-                        match Package::load_from_file(&path, None) {
-                            Ok(pkg) => {
-                                debug!("Loaded package (flat): {}-{}", pkg.name, pkg.version);
-                                self.package_cache
-                                    .entry(pkg.name.clone())
-                                    .or_default()
-                                    .insert(pkg.version.clone(), pkg);
-                            }
-                            Err(e) => {
-                                warn!("Failed to load package {:?}: {}", path, e);
-                            }
-                        }
-                    }
-                } else if path.is_dir() {
-                    for version_entry in std::fs::read_dir(&path)? {
-                        let version_entry = version_entry?;
-                        let version_dir = version_entry.path();
-
-                        if !version_dir.is_dir() {
-                            continue;
-                        }
-
-                        let package_file = version_dir.join("package.yaml");
-                        if !package_file.exists() {
-                            continue;
-                        }
-
-                        match Package::load(&version_dir) {
-                            Ok(pkg) => {
-                                debug!("Loaded package (nested): {}-{}", pkg.name, pkg.version);
-                                self.package_cache
-                                    .entry(pkg.name.clone())
-                                    .or_default()
-                                    .insert(pkg.version.clone(), pkg);
-                            }
-                            Err(e) => {
-                                warn!("Failed to load package {:?}: {}", version_dir, e);
-                            }
-                        }
-                    }
+    /// Keys of the config overrides applied to a package id, in config order.
+    pub fn applied_overrides(&self, id: &str) -> Vec<String> {
+        self.overridden.borrow().get(id).cloned().unwrap_or_default()
+    }
+
+    /// Apply include/exclude filters from config, per package version.
+    fn apply_filters(&self, packages: &mut HashMap<String, HashMap<String, Package>>) {
+        let filters = &self.config.filters;
+        if filters.include.is_empty() && filters.exclude.is_empty() {
+            return;
+        }
+
+        let mut hidden = self.hidden.borrow_mut();
+        for (name, versions) in packages.iter_mut() {
+            versions.retain(|version, _| match filters.hidden_by(name, version) {
+                Some(rule) => {
+                    debug!("Filtered out {}-{}: {}", name, version, rule);
+                    hidden.push(HiddenPackage {
+                        name: name.clone(),
+                        version: version.clone(),
+                        rule,
+                    });
+                    false
                 }
-            }
+                None => true,
+            });
         }
+        packages.retain(|_, versions| !versions.is_empty());
+    }
 
-        info!("Loaded {} packages", self.package_cache.len());
-        Ok(())
+    /// Package versions hidden by the config filters, sorted by id.
+    pub fn hidden_packages(&self) -> Vec<HiddenPackage> {
+        let mut hidden = self.hidden.borrow().clone();
+        hidden.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        hidden
     }
 
     /// Resolve a list of package requests
     pub fn resolve(&self, requests: &[String]) -> Result<ResolvedPackages> {
+        let key = self.resolve_key(requests);
+        if let Some(packages) = self.cached_resolve(key) {
+            info!("Using cached resolve");
+            return Ok(ResolvedPackages {
+                packages,
+                base: self.base_environment(),
+                platform: self.platform.clone(),
+            });
+        }
+
         let mut resolved: Vec<Package> = Vec::new();
         let mut seen: std::collections::HashSet<String> = std::collections::HashSet::new();
 
         // Expand aliases
         let mut expanded_requests: Vec<String> = Vec::new();
         for req in requests {
-            if let Some(alias_packages) = self.config.resolve_alias(req) {
+            if let Some(alias_packages) = self.config.resolve_alias(req)? {
                 expanded_requests.extend(alias_packages);
             } else {
                 expanded_requests.push(req.clone());
@@ -241,7 +502,96 @@ impl Resolver {
             self.resolve_request(&request, &mut resolved, &mut seen)?;
         }
 
-        Ok(ResolvedPackages { packages: resolved })
+        self.store_resolve(key, &resolved);
+
+        Ok(ResolvedPackages {
+            packages: resolved,
+            base: self.base_environment(),
+            platform: self.platform.clone(),
+        })
+    }
+
+    /// The inherited environment packages are layered on.  The caller's
+    /// variables mean nothing on another operating system, so resolving
+    /// for one starts from an empty environment.
+    fn base_environment(&self) -> HashMap<String, String> {
+        if self.platform.os != Platform::current().os {
+            debug!("Resolving for {}: not inheriting the caller's environment", self.platform);
+            return HashMap::new();
+        }
+        self.config.inherit.base_environment()
+    }
+
+    /// Cache key of a resolve: everything besides the package files that
+    /// decides its outcome.
+    fn resolve_key(&self, requests: &[String]) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        self.platform.hash(&mut hasher);
+        requests.hash(&mut hasher);
+        let pins: BTreeMap<_, _> = self.pins.iter().collect();
+        pins.hash(&mut hasher);
+        self.config.all_package_paths().hash(&mut hasher);
+        format!("{:?}", self.config.filters).hash(&mut hasher);
+        format!("{:?}", self.config.overrides).hash(&mut hasher);
+        // Cross-platform resolves bake the mapped roots into the packages.
+        format!("{:?}", self.config.path_mappings).hash(&mut hasher);
+        let aliases: BTreeMap<_, _> = self.config.aliases.iter().collect();
+        aliases.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// A cached resolve for `key`, if every family it looked up still has
+    /// the same sources with the same fingerprints.  Only lazy resolvers
+    /// use the resolve cache: they already hold the directory listing the
+    /// check needs.
+    fn cached_resolve(&self, key: u64) -> Option<Vec<Package>> {
+        if self.refresh {
+            return None;
+        }
+        let dir = self.config.cache_root()?;
+        let pending = self.pending.borrow();
+        let pending = pending.as_ref()?;
+        let entry = cache::load_resolve(&dir, key)?;
+
+        let consulted = self.consulted.borrow();
+        let fresh = entry.families.iter().all(|(name, recorded)| {
+            pending
+                .get(name)
+                .or_else(|| consulted.get(name))
+                .is_some_and(|sources| cache::fingerprint_sources(sources) == *recorded)
+        });
+        if !fresh {
+            debug!("Cached resolve {:016x} is stale", key);
+            return None;
+        }
+        Some(entry.packages)
+    }
+
+    /// Remember a resolve.  Skipped when the lazy resolver had to fall back
+    /// to a full scan, since the listing can't vouch for the result then.
+    fn store_resolve(&self, key: u64, packages: &[Package]) {
+        let Some(dir) = self.config.cache_root() else {
+            return;
+        };
+        if self.pending.borrow().is_none() {
+            return;
+        }
+        let families = self
+            .consulted
+            .borrow()
+            .iter()
+            .map(|(name, sources)| (name.clone(), cache::fingerprint_sources(sources)))
+            .collect();
+        let entry = cache::ResolveEntry {
+            families,
+            packages: packages.to_vec(),
+        };
+        if let Err(e) = cache::save_resolve(&dir, key, &entry) {
+            debug!("Failed to save resolve cache: {}", e);
+        }
     }
 
     /// Resolve a single package request (with dependencies)
@@ -273,7 +623,9 @@ impl Resolver {
 
     /// Find a package matching a request, preferring a pinned version.
     fn find_package(&self, request: &PackageRequest) -> Result<Package> {
-        let versions = self.package_cache.get(&request.name)
+        self.ensure_loaded(&request.name)?;
+        let cache = self.package_cache.borrow();
+        let versions = cache.get(&request.name)
             .ok_or_else(|| anyhow::anyhow!("Package not found: {}", request.name))?;
 
         // Lockfile pin takes priority
@@ -317,14 +669,19 @@ impl Resolver {
 
     /// List all available packages
     pub fn list_packages(&self) -> Result<Vec<String>> {
-        let mut packages: Vec<String> = self.package_cache.keys().cloned().collect();
+        if self.pending.borrow().is_some() {
+            self.load_packages()?;
+        }
+        let mut packages: Vec<String> = self.package_cache.borrow().keys().cloned().collect();
         packages.sort();
         Ok(packages)
     }
 
     /// List versions of a specific package
     pub fn list_versions(&self, name: &str) -> Result<Vec<String>> {
-        let versions = self.package_cache.get(name)
+        self.ensure_loaded(name)?;
+        let cache = self.package_cache.borrow();
+        let versions = cache.get(name)
             .ok_or_else(|| anyhow::anyhow!("Package not found: {}", name))?;
 
         let mut version_list: Vec<String> = versions.keys().cloned().collect();
@@ -353,7 +710,7 @@ impl Resolver {
 
         // Check command targets.  Expand ${PACKAGE_ROOT}, ${NAME}, etc.
         // against the package's own env, then tokenize and check the program.
-        let base_env: HashMap<String, String> = std::env::vars().collect();
+        let base_env = self.config.inherit.base_environment();
         let pkg_env = package.resolved_environment(&base_env);
         let mut problems: Vec<String> = Vec::new();
         for (alias, target) in &package.commands {
@@ -379,6 +736,7 @@ impl Resolver {
 
     /// Back-compat shim: treat any command-target problems as errors.
     #[cfg(test)]
+    #[allow(dead_code)]
     pub fn validate_package(&self, id: &str) -> Result<()> {
         let problems = self.validate_package_report(id)?;
         if !problems.is_empty() {
@@ -390,7 +748,7 @@ impl Resolver {
 
 /// Check that `program` is an existing file that is executable.  Looks up
 /// bare names (no slash) on `PATH` via the `which` crate.
-fn check_executable(program: &str) -> std::result::Result<(), String> {
+pub fn check_executable(program: &str) -> std::result::Result<(), String> {
     let path = std::path::Path::new(program);
     let resolved: std::path::PathBuf = if path.components().count() > 1 || path.is_absolute() {
         if !path.exists() {
@@ -427,3 +785,46 @@ fn check_executable(program: &str) -> std::result::Result<(), String> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_scan_after_lazy_load_keeps_single_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = dir.path().join("packages");
+        for version in ["2023", "2024"] {
+            let root = packages.join("maya").join(version);
+            std::fs::create_dir_all(&root).unwrap();
+            std::fs::write(
+                root.join("package.yaml"),
+                format!("name: maya\nversion: \"{}\"\n", version),
+            )
+            .unwrap();
+        }
+        let config: Config = serde_yaml::from_str(&format!(
+            "package_paths: [{}]\n\
+             cache_dir: {}\n\
+             filters:\n  exclude: [maya-2023]\n\
+             overrides:\n  maya-2024:\n    environment:\n      STUDIO: \"1\"\n",
+            packages.display(),
+            dir.path().join("cache").display()
+        ))
+        .unwrap();
+
+        let resolver = Resolver::new_lazy(&config, false).unwrap();
+        assert_eq!(resolver.list_versions("maya").unwrap(), vec!["2024"]);
+        // Not in the directory listing: falls back to a full scan.
+        assert!(resolver.list_versions("houdini").is_err());
+        assert!(resolver.pending.borrow().is_none());
+
+        assert_eq!(resolver.applied_overrides("maya-2024"), vec!["maya-2024"]);
+        let hidden: Vec<String> = resolver
+            .hidden_packages()
+            .iter()
+            .map(|h| format!("{}-{}", h.name, h.version))
+            .collect();
+        assert_eq!(hidden, vec!["maya-2023"]);
+    }
+}
@@ -1,6 +1,6 @@
 //! Shell spawning and detection
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::SystemTime;
@@ -11,8 +11,36 @@ use anyhow::{Context, Result};
 /// orphans reliably.
 pub const SHIM_DIR_PREFIX: &str = "anvil-shell-";
 
+/// Shells anvil knows how to drive, by executable name.
+pub const KNOWN_SHELLS: &[&str] = &[
+    "bash", "zsh", "fish", "tcsh", "csh", "nu", "elvish", "xonsh", "pwsh", "powershell",
+];
+
+/// A shell's executable name: `/bin/tcsh` and `tcsh.exe` are both `tcsh`.
+pub fn shell_name(shell: &str) -> &str {
+    let name = std::path::Path::new(shell)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(shell);
+    name.strip_suffix(".exe").unwrap_or(name)
+}
+
 /// Detect the user's preferred shell
 pub fn detect_shell() -> String {
+    // nushell and xonsh export their version; they're rarely the login
+    // shell, so $SHELL would name whatever started them.
+    if std::env::var_os("NU_VERSION").is_some() {
+        return "nu".to_string();
+    }
+    if std::env::var_os("XONSH_VERSION").is_some() {
+        return "xonsh".to_string();
+    }
+
+    // The shell anvil was typed into, when that's one we know.
+    if let Some(shell) = parent_shell() {
+        return shell;
+    }
+
     // Check SHELL environment variable
     if let Ok(shell) = std::env::var("SHELL") {
         return shell;
@@ -31,12 +59,24 @@ pub fn detect_shell() -> String {
     "bash".to_string()
 }
 
+/// The parent process's name, if it's one of [`KNOWN_SHELLS`].  `sh` is
+/// left out: a parent `sh` is almost always a script, not a prompt.
+#[cfg(target_os = "linux")]
+fn parent_shell() -> Option<String> {
+    let ppid = std::os::unix::process::parent_id();
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", ppid)).ok()?;
+    let name = comm.trim();
+    KNOWN_SHELLS.contains(&name).then(|| name.to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn parent_shell() -> Option<String> {
+    None
+}
+
 /// Spawn an interactive shell with the given environment
 pub fn spawn_shell(shell: &str, env: &HashMap<String, String>) -> Result<()> {
-    let shell_name = std::path::Path::new(shell)
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or(shell);
+    let shell_name = shell_name(shell);
     
     println!("Starting {} shell with resolved environment...", shell_name);
     println!("Type 'exit' to return to your original shell.\n");
@@ -48,12 +88,53 @@ pub fn spawn_shell(shell: &str, env: &HashMap<String, String>) -> Result<()> {
     cmd.envs(env);
     
     // Add anvil indicator to prompt
-    if let Some(prompt) = env.get("PS1") {
-        let new_prompt = format!("[anvil] {}", prompt);
-        cmd.env("PS1", new_prompt);
-    } else {
-        // Set a simple prompt for bash
-        cmd.env("PS1", "[anvil] \\u@\\h:\\w\\$ ");
+    cmd.env("ANVIL_SHELL", "1");
+    match shell_name {
+        "nu" => {
+            // The prompt is a closure (or string) set by the user's config,
+            // so wrap it once the config has loaded.
+            cmd.args(["-e", NU_PROMPT]);
+        }
+        "xonsh" => {
+            let prompt = env
+                .get("PROMPT")
+                .map(String::as_str)
+                .unwrap_or(XONSH_DEFAULT_PROMPT);
+            cmd.env("PROMPT", format!("[anvil] {}", prompt));
+        }
+        "tcsh" | "csh" => {
+            // The prompt is a shell variable only an rc file can set, and
+            // csh has no flag naming one: point HOME at a wrapper rc that
+            // restores it, reads the user's own and prefixes the prompt.
+            let home = env
+                .get("HOME")
+                .cloned()
+                .or_else(|| dirs::home_dir().map(|h| h.to_string_lossy().into_owned()));
+            if let Some(home) = home {
+                let dir = startup_dir()?;
+                let rc = csh_prompt_rc(shell_name, &home);
+                let file = if shell_name == "tcsh" { ".tcshrc" } else { ".cshrc" };
+                std::fs::write(dir.join(file), rc)?;
+                cmd.env("HOME", &dir);
+            }
+        }
+        "elvish" => {
+            // `-rc` replaces the user's rc.elv, so the wrapper carries a
+            // copy of it followed by the prompt prefix.
+            let user_rc = elvish_rc_path().and_then(|p| std::fs::read_to_string(p).ok());
+            let path = startup_dir()?.join("rc.elv");
+            std::fs::write(&path, elvish_prompt_rc(user_rc.as_deref()))?;
+            cmd.arg("-rc").arg(&path);
+        }
+        _ => {
+            if let Some(prompt) = env.get("PS1") {
+                let new_prompt = format!("[anvil] {}", prompt);
+                cmd.env("PS1", new_prompt);
+            } else {
+                // Set a simple prompt for bash
+                cmd.env("PS1", "[anvil] \\u@\\h:\\w\\$ ");
+            }
+        }
     }
     
     // Platform-specific setup
@@ -74,56 +155,324 @@ pub fn spawn_shell(shell: &str, env: &HashMap<String, String>) -> Result<()> {
     }
 }
 
-/// Generate shell-specific environment setup script
-pub fn generate_env_script(shell: &str, env: &HashMap<String, String>) -> String {
-    let shell_name = std::path::Path::new(shell)
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or(shell);
-    
-    match shell_name {
-        "bash" | "sh" | "zsh" => {
-            let mut script = String::new();
-            for (key, value) in env {
-                // Escape special characters
-                let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
-                script.push_str(&format!("export {}=\"{}\"\n", key, escaped));
-            }
-            script
+/// A fresh directory for a shell's startup files.  Like the shim dirs it
+/// outlives this process and is reclaimed by the sweeper.
+fn startup_dir() -> Result<PathBuf> {
+    Ok(tempfile::Builder::new()
+        .prefix(SHIM_DIR_PREFIX)
+        .tempdir()
+        .context("Failed to create startup tempdir")?
+        .keep())
+}
+
+/// A csh/tcsh rc file that sets `HOME` back to `home`, sources the user's
+/// own rc file and prefixes the prompt.
+fn csh_prompt_rc(shell: &str, home: &str) -> String {
+    let rc_files: &[&str] = if shell == "tcsh" {
+        &[".tcshrc", ".cshrc"]
+    } else {
+        &[".cshrc"]
+    };
+    let mut rc = format!("setenv HOME {}\n", csh_quote(home));
+    for (i, file) in rc_files.iter().enumerate() {
+        let keyword = if i == 0 { "if" } else { "else if" };
+        rc.push_str(&format!(
+            "{} ( -r \"$HOME/{}\" ) then\n    source \"$HOME/{}\"\n",
+            keyword, file, file
+        ));
+    }
+    rc.push_str("endif\nif ( $?prompt ) set prompt=\"[anvil] $prompt:q\"\n");
+    rc
+}
+
+/// Where elvish reads its rc file: `$XDG_CONFIG_HOME/elvish/rc.elv` (or
+/// the platform config dir), then the legacy `~/.elvish/rc.elv`.
+fn elvish_rc_path() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => dirs::config_dir()?,
+        None => dirs::home_dir()?.join(".config"),
+    };
+    let legacy = dirs::home_dir()?.join(".elvish").join("rc.elv");
+    [config.join("elvish").join("rc.elv"), legacy]
+        .into_iter()
+        .find(|p| p.is_file())
+}
+
+/// The user's elvish rc followed by a prompt prefix.
+fn elvish_prompt_rc(user_rc: Option<&str>) -> String {
+    let mut rc = user_rc.unwrap_or_default().to_string();
+    if !rc.is_empty() && !rc.ends_with('\n') {
+        rc.push('\n');
+    }
+    rc.push_str("var __anvil_prompt = $edit:prompt\n");
+    rc.push_str("set edit:prompt = { put '[anvil] '; $__anvil_prompt }\n");
+    rc
+}
+
+/// Prefixes nushell's prompt with `[anvil] `, whether `PROMPT_COMMAND` is a
+/// closure or a plain string.
+const NU_PROMPT: &str = "let __anvil_prompt = ($env.PROMPT_COMMAND? | default ''); \
+    $env.PROMPT_COMMAND = {|| \
+        let inner = if ($__anvil_prompt | describe) == 'closure' { do $__anvil_prompt } else { $__anvil_prompt }; \
+        $'[anvil] ($inner)' \
+    }";
+
+/// xonsh's built-in `$PROMPT`, used when the environment doesn't set one.
+const XONSH_DEFAULT_PROMPT: &str = "{env_name}{BOLD_GREEN}{user}@{hostname}{BOLD_BLUE} {cwd}{branch_color}{curr_branch: {}}{RESET} {BOLD_BLUE}{prompt_end}{RESET} ";
+
+/// Formats `--format` accepts on `anvil env` and `anvil context show`.
+pub const ENV_FORMATS: &[&str] = &[
+    "sh",
+    "dotenv",
+    "dockerfile",
+    "systemd",
+    "github-actions",
+    "powershell",
+    "fish",
+    "csh",
+    "nu",
+    "elvish",
+    "xonsh",
+];
+
+/// `--format`s that produce a file for another tool rather than code for
+/// the current shell.  They carry only the variables packages set, never
+/// the caller's whole environment.
+pub const ARTIFACT_FORMATS: &[&str] = &["dotenv", "dockerfile", "systemd", "github-actions"];
+
+/// Render `env` as a script or config snippet in `format`: a shell name
+/// (`bash`, `zsh`, `fish`, `pwsh`, `csh`, `nu`, `elvish`, `xonsh`, `cmd`,
+/// ...; a path is reduced to its file name) or one of `dotenv`, `dockerfile`, `systemd` and
+/// `github-actions`.  Variables are sorted by name and every value is
+/// quoted for the target, so reading the output back reproduces `env`
+/// exactly.  Variables the target can't represent (a name that isn't a
+/// plain identifier, a newline where none fits) are left out with a
+/// warning rather than written in a form that could run code.
+pub fn generate_env_script(format: &str, env: &HashMap<String, String>) -> String {
+    let format = shell_name(format);
+
+    let mut vars: Vec<(&String, &String)> = env.iter().collect();
+    vars.sort();
+
+    let mut script = String::new();
+    for (key, value) in vars {
+        if let Some(line) = set_line(format, key, value) {
+            script.push_str(&line);
+            script.push('\n');
         }
+    }
+    script
+}
+
+/// Shells `anvil activate` and `anvil deactivate` can write for.
+pub const ACTIVATE_SHELLS: &[&str] = &[
+    "bash", "sh", "zsh", "fish", "tcsh", "csh", "nu", "elvish", "xonsh", "pwsh", "powershell",
+];
+
+/// Render a set of changes for the shell `format`: `Some(value)` sets the
+/// variable, `None` unsets it.
+pub fn generate_env_changes(format: &str, changes: &BTreeMap<String, Option<String>>) -> String {
+    let mut script = String::new();
+    for (key, value) in changes {
+        let line = match value {
+            Some(value) => set_line(format, key, value),
+            None => unset_line(format, key),
+        };
+        if let Some(line) = line {
+            script.push_str(&line);
+            script.push('\n');
+        }
+    }
+    script
+}
+
+/// Whether `key` is a plain identifier (`[A-Za-z_][A-Za-z0-9_]*`), the only
+/// names every target accepts unquoted.
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The line setting `key` to `value` in `format`, or `None` (with a
+/// warning) when it can't be written safely.
+fn set_line(format: &str, key: &str, value: &str) -> Option<String> {
+    if !is_identifier(key) {
+        tracing::warn!("Skipping {:?}: not a valid variable name for {}", key, format);
+        return None;
+    }
+    if value.contains('\n') && matches!(format, "cmd" | "dockerfile") {
+        tracing::warn!("Skipping {}: {} values can't span lines", key, format);
+        return None;
+    }
+    if key == "PWD" && format == "nu" {
+        // nushell owns $env.PWD and refuses assignments to it.
+        return None;
+    }
+
+    let line = match format {
         "fish" => {
-            let mut script = String::new();
-            for (key, value) in env {
-                let escaped = value.replace('\\', "\\\\").replace('\'', "\\'");
-                script.push_str(&format!("set -gx {} '{}'\n", key, escaped));
-            }
-            script
+            // Fish keeps *PATH variables as lists; set one element per
+            // segment so `$PATH` isn't a single colon-joined entry.
+            let parts: Vec<String> = if key.ends_with("PATH") {
+                value.split(':').map(fish_quote).collect()
+            } else {
+                vec![fish_quote(value)]
+            };
+            format!("set -gx {} {}", key, parts.join(" "))
         }
-        "pwsh" | "powershell" => {
-            let mut script = String::new();
-            for (key, value) in env {
-                let escaped = value.replace('\'', "''");
-                script.push_str(&format!("$env:{} = '{}'\n", key, escaped));
+        "pwsh" | "powershell" => format!("$env:{} = {}", key, powershell_quote(value)),
+        // A trailing `;` keeps the lines apart under
+        // `` eval "`anvil activate`" ``, which joins them into one.
+        "csh" | "tcsh" => format!("setenv {} {};", key, csh_quote(value)),
+        "nu" => {
+            // nushell keeps PATH as a list and only converts it back for
+            // child processes.
+            if key == "PATH" || key == "Path" {
+                let parts: Vec<String> = std::env::split_paths(value)
+                    .map(|part| nu_quote(&part.to_string_lossy()))
+                    .collect();
+                format!("$env.{} = [{}]", key, parts.join(" "))
+            } else {
+                format!("$env.{} = {}", key, nu_quote(value))
             }
-            script
         }
-        "cmd" => {
-            let mut script = String::new();
-            for (key, value) in env {
-                script.push_str(&format!("set {}={}\n", key, value));
+        "elvish" => format!("set-env {} {}", key, elvish_quote(value)),
+        "xonsh" => format!("${} = {}", key, python_quote(value)),
+        // Everything between the first and last quote is taken verbatim,
+        // except `%`, which still expands in a batch file.
+        "cmd" => format!("set \"{}={}\"", key, value.replace('%', "%%")),
+        "dotenv" => format!("{}=\"{}\"", key, escape_double_quoted(value, "$", Some("\\n"))),
+        "dockerfile" => format!("ENV {}=\"{}\"", key, escape_double_quoted(value, "$", None)),
+        "systemd" => format!(
+            "Environment=\"{}={}\"",
+            key,
+            escape_double_quoted(value, "", Some("\\n")).replace('%', "%%")
+        ),
+        "github-actions" => github_env_entry(key, value),
+        // bash, sh, zsh and anything unknown.  A newline is fine as is
+        // inside double quotes.
+        _ => format!("export {}=\"{}\"", key, escape_double_quoted(value, "$`", None)),
+    };
+    Some(line)
+}
+
+/// The line unsetting `key` in the shell `format`.
+fn unset_line(format: &str, key: &str) -> Option<String> {
+    if !is_identifier(key) {
+        tracing::warn!("Skipping {:?}: not a valid variable name for {}", key, format);
+        return None;
+    }
+    let line = match format {
+        "fish" => format!("set -e {}", key),
+        "pwsh" | "powershell" => format!("Remove-Item Env:{} -ErrorAction SilentlyContinue", key),
+        "csh" | "tcsh" => format!("unsetenv {};", key),
+        "nu" if key == "PWD" => return None,
+        "nu" => format!("hide-env -i {}", key),
+        "elvish" => format!("unset-env {}", key),
+        "xonsh" => format!("${{...}}.pop('{}', None)", key),
+        "cmd" => format!("set {}=", key),
+        _ => format!("unset {}", key),
+    };
+    Some(line)
+}
+
+/// Escape a value for a double-quoted string: backslashes, quotes and the
+/// characters in `special` (which the target would otherwise expand) get a
+/// backslash; newlines become `newline`, or stay as they are.
+fn escape_double_quoted(value: &str, special: &str, newline: Option<&str>) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | '"' => {
+                out.push('\\');
+                out.push(c);
             }
-            script
+            '\n' => match newline {
+                Some(escaped) => out.push_str(escaped),
+                None => out.push(c),
+            },
+            c if special.contains(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
         }
-        _ => {
-            // Default to bash-style
-            let mut script = String::new();
-            for (key, value) in env {
-                let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
-                script.push_str(&format!("export {}=\"{}\"\n", key, escaped));
+    }
+    out
+}
+
+/// Single-quote for fish, where only `\` and `'` are special inside.
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Single-quote for PowerShell, which doubles a quote to escape it and
+/// also treats the typographic single quotes as quotes.
+fn powershell_quote(value: &str) -> String {
+    let mut out = String::from("'");
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            out.push(c);
+        }
+        out.push(c);
+    }
+    out.push('\'');
+    out
+}
+
+/// Single-quote for csh/tcsh.  A quote can't be escaped inside quotes, so
+/// it closes, adds `\'` and reopens; a newline would end the command, so
+/// it gets a backslash.  (`!` is left alone: history substitution only
+/// applies to typed input, not to sourced or `eval`ed output.)
+fn csh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''").replace('\n', "\\\n"))
+}
+
+/// Double-quote for nushell, whose plain double-quoted strings take
+/// backslash escapes but don't interpolate.
+fn nu_quote(value: &str) -> String {
+    format!("\"{}\"", escape_double_quoted(value, "", Some("\\n")))
+}
+
+/// Single-quote for elvish, where a doubled quote is the only escape.
+fn elvish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// A Python string literal for xonsh.  Control characters are escaped so
+/// the literal stays on one line.
+fn python_quote(value: &str) -> String {
+    let mut out = String::from("'");
+    for c in value.chars() {
+        match c {
+            '\\' | '\'' => {
+                out.push('\\');
+                out.push(c);
             }
-            script
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
         }
     }
+    out.push('\'');
+    out
+}
+
+/// A `$GITHUB_ENV` entry: `KEY=value`, or the heredoc form for values
+/// spanning lines, with a delimiter that doesn't occur in the value.
+fn github_env_entry(key: &str, value: &str) -> String {
+    if !value.contains('\n') {
+        return format!("{}={}", key, value);
+    }
+    let mut delimiter = "ANVIL_EOF".to_string();
+    while value.lines().any(|line| line == delimiter) {
+        delimiter.push('_');
+    }
+    format!("{}<<{}\n{}\n{}", key, delimiter, value, delimiter)
 }
 
 /// Write a PATH shim for each `(alias, command)` pair into a fresh tempdir
@@ -159,7 +508,10 @@ fn write_shim(dir: &Path, alias: &str, target: &str) -> Result<()> {
     // `"/Applications/... Painter" --flag`.  `exec "$@"` with the command
     // embedded raw preserves any baked-in arguments and lets the user append
     // their own.
-    let script = format!("#!/usr/bin/env bash\nexec {} \"$@\"\n", target);
+    //
+    // `/bin/sh` rather than bash: the shim runs the same from any shell,
+    // including on hosts where bash isn't installed.
+    let script = format!("#!/bin/sh\nexec {} \"$@\"\n", target);
     let path = dir.join(alias);
     std::fs::write(&path, script)?;
     let mut perms = std::fs::metadata(&path)?.permissions();
@@ -332,4 +684,279 @@ mod tests {
         prepend_path(&mut env, Path::new("/tmp/shim"));
         assert_eq!(env["PATH"], "/tmp/shim");
     }
+
+    fn env_of(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn env_script_formats_escape_values() {
+        let env = env_of(&[("A", r#"say "hi" $HOME\n"#), ("B", "50%")]);
+
+        assert_eq!(
+            generate_env_script("/bin/bash", &env),
+            "export A=\"say \\\"hi\\\" \\$HOME\\\\n\"\nexport B=\"50%\"\n"
+        );
+        assert_eq!(
+            generate_env_script("dotenv", &env),
+            "A=\"say \\\"hi\\\" \\$HOME\\\\n\"\nB=\"50%\"\n"
+        );
+        assert_eq!(
+            generate_env_script("dockerfile", &env),
+            "ENV A=\"say \\\"hi\\\" \\$HOME\\\\n\"\nENV B=\"50%\"\n"
+        );
+        assert_eq!(
+            generate_env_script("systemd", &env),
+            "Environment=\"A=say \\\"hi\\\" $HOME\\\\n\"\nEnvironment=\"B=50%%\"\n"
+        );
+
+        let env = env_of(&[("Q", "it's !")]);
+        assert_eq!(generate_env_script("csh", &env), "setenv Q 'it'\\''s !';\n");
+        assert_eq!(generate_env_script("fish", &env), "set -gx Q 'it\\'s !'\n");
+        assert_eq!(generate_env_script("powershell", &env), "$env:Q = 'it''s !'\n");
+    }
+
+    #[test]
+    fn env_script_formats_for_more_shells() {
+        let env = env_of(&[("Q", "it's \"$x\"\\\n")]);
+        assert_eq!(generate_env_script("tcsh", &env), "setenv Q 'it'\\''s \"$x\"\\\\\n';\n");
+        assert_eq!(generate_env_script("nu", &env), "$env.Q = \"it's \\\"$x\\\"\\\\\\n\"\n");
+        assert_eq!(generate_env_script("elvish", &env), "set-env Q 'it''s \"$x\"\\\n'\n");
+        assert_eq!(generate_env_script("xonsh", &env), "$Q = 'it\\'s \"$x\"\\\\\\n'\n");
+
+        // nushell's PATH is a list, and it won't take an assignment to PWD.
+        let env = env_of(&[("PATH", "/a:/b"), ("PWD", "/tmp")]);
+        #[cfg(unix)]
+        assert_eq!(generate_env_script("nu", &env), "$env.PATH = [\"/a\" \"/b\"]\n");
+    }
+
+    #[test]
+    fn env_changes_for_more_shells() {
+        let mut changes = BTreeMap::new();
+        changes.insert("GONE".to_string(), None);
+        changes.insert("KEPT".to_string(), Some("a b".to_string()));
+        assert_eq!(
+            generate_env_changes("csh", &changes),
+            "unsetenv GONE;\nsetenv KEPT 'a b';\n"
+        );
+        assert_eq!(
+            generate_env_changes("nu", &changes),
+            "hide-env -i GONE\n$env.KEPT = \"a b\"\n"
+        );
+        assert_eq!(
+            generate_env_changes("elvish", &changes),
+            "unset-env GONE\nset-env KEPT 'a b'\n"
+        );
+        assert_eq!(
+            generate_env_changes("xonsh", &changes),
+            "${...}.pop('GONE', None)\n$KEPT = 'a b'\n"
+        );
+    }
+
+    #[test]
+    fn shell_names_drop_path_and_extension() {
+        assert_eq!(shell_name("/bin/tcsh"), "tcsh");
+        assert_eq!(shell_name("nu.exe"), "nu");
+        assert_eq!(shell_name("xonsh"), "xonsh");
+    }
+
+    #[test]
+    fn csh_prompt_rc_restores_home_and_reads_user_rc() {
+        assert_eq!(
+            csh_prompt_rc("tcsh", "/home/o'neil"),
+            "setenv HOME '/home/o'\\''neil'\n\
+             if ( -r \"$HOME/.tcshrc\" ) then\n    source \"$HOME/.tcshrc\"\n\
+             else if ( -r \"$HOME/.cshrc\" ) then\n    source \"$HOME/.cshrc\"\n\
+             endif\n\
+             if ( $?prompt ) set prompt=\"[anvil] $prompt:q\"\n"
+        );
+        assert!(!csh_prompt_rc("csh", "/home/a").contains(".tcshrc"));
+    }
+
+    #[test]
+    fn elvish_prompt_rc_keeps_user_rc() {
+        let rc = elvish_prompt_rc(Some("use str"));
+        assert!(rc.starts_with("use str\nvar __anvil_prompt = $edit:prompt\n"), "{}", rc);
+        assert!(rc.ends_with("set edit:prompt = { put '[anvil] '; $__anvil_prompt }\n"));
+        assert!(elvish_prompt_rc(None).starts_with("var __anvil_prompt"));
+    }
+
+    /// Start an interactive tcsh the way `spawn_shell` does and read the
+    /// prompt and HOME back.  Skipped when tcsh isn't installed.
+    #[cfg(unix)]
+    #[test]
+    fn tcsh_prompt_rc_sets_the_prompt() {
+        use std::io::Write;
+
+        let Ok(tcsh) = which::which("tcsh") else {
+            return;
+        };
+        let home = tempfile::tempdir().unwrap();
+        std::fs::write(home.path().join(".tcshrc"), "set prompt=\"user> \"\n").unwrap();
+        let home = home.path().to_string_lossy().into_owned();
+        let wrapper = tempfile::tempdir().unwrap();
+        std::fs::write(wrapper.path().join(".tcshrc"), csh_prompt_rc("tcsh", &home)).unwrap();
+
+        let mut child = Command::new(tcsh)
+            .arg("-i")
+            .env("HOME", wrapper.path())
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"echo \"prompt=$prompt:q\"\necho \"home=$HOME\"\nexit\n")
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("prompt=[anvil] user> "), "{}", stdout);
+        assert!(stdout.contains(&format!("home={}", home)), "{}", stdout);
+    }
+
+    #[test]
+    fn fish_splits_path_lists() {
+        let env = env_of(&[("PATH", "/a:/b")]);
+        assert_eq!(generate_env_script("fish", &env), "set -gx PATH '/a' '/b'\n");
+    }
+
+    #[test]
+    fn github_actions_uses_heredoc_for_multiline() {
+        let env = env_of(&[("ONE", "x=y"), ("TWO", "line1\nANVIL_EOF\nline3")]);
+        assert_eq!(
+            generate_env_script("github-actions", &env),
+            "ONE=x=y\nTWO<<ANVIL_EOF_\nline1\nANVIL_EOF\nline3\nANVIL_EOF_\n"
+        );
+    }
+
+    #[test]
+    fn env_changes_set_and_unset() {
+        let mut changes = BTreeMap::new();
+        changes.insert("GONE".to_string(), None);
+        changes.insert("KEPT".to_string(), Some("a b".to_string()));
+        assert_eq!(
+            generate_env_changes("zsh", &changes),
+            "unset GONE\nexport KEPT=\"a b\"\n"
+        );
+        assert_eq!(
+            generate_env_changes("fish", &changes),
+            "set -e GONE\nset -gx KEPT 'a b'\n"
+        );
+        assert_eq!(
+            generate_env_changes("pwsh", &changes),
+            "Remove-Item Env:GONE -ErrorAction SilentlyContinue\n$env:KEPT = 'a b'\n"
+        );
+    }
+
+    #[test]
+    fn unrepresentable_variables_are_skipped() {
+        let env = env_of(&[("BAD NAME", "x"), ("$(id)", "x"), ("MULTI", "a\nb"), ("OK", "1")]);
+        assert_eq!(generate_env_script("bash", &env), "export MULTI=\"a\nb\"\nexport OK=\"1\"\n");
+        assert_eq!(generate_env_script("dockerfile", &env), "ENV OK=\"1\"\n");
+        assert_eq!(generate_env_script("cmd", &env), "set \"OK=1\"\n");
+        assert_eq!(generate_env_script("dotenv", &env), "MULTI=\"a\\nb\"\nOK=\"1\"\n");
+    }
+
+    #[test]
+    fn powershell_doubles_typographic_quotes() {
+        let env = env_of(&[("Q", "\u{2018}x\u{2019}")]);
+        assert_eq!(
+            generate_env_script("pwsh", &env),
+            "$env:Q = '\u{2018}\u{2018}x\u{2019}\u{2019}'\n"
+        );
+    }
+
+    /// Values that break naive quoting or would run code if re-expanded.
+    fn hostile_env() -> HashMap<String, String> {
+        env_of(&[
+            ("DOLLAR", "$HOME and ${HOME} $$"),
+            ("SUBST", "`id` $(touch pwned)"),
+            ("QUOTES", "it's \"quoted\""),
+            ("BACKSLASH", "C:\\path\\ trailing\\"),
+            ("NEWLINE", "line1\nline2\n"),
+            ("BANG", "wow!! !$"),
+            ("PERCENT", "100% %PATH%"),
+            ("CURLY", "\u{2018}curly\u{2019}"),
+            ("GLOB", "* ? [a]"),
+            ("DASH", "-n -e"),
+            ("EMPTY", ""),
+            ("SEMI", "a; echo pwned"),
+            ("TOOLPATH", "/opt/a b:/opt/c"),
+        ])
+    }
+
+    /// Source the `format` script for `env` in `shell`, then read the
+    /// exported environment back.  `None` when the shell isn't installed.
+    #[cfg(target_os = "linux")]
+    fn round_trip(shell: &str, format: &str, env: &HashMap<String, String>) -> Option<HashMap<String, String>> {
+        let shell_path = which::which(shell).ok()?;
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("env.script");
+        std::fs::write(&script, generate_env_script(format, env)).unwrap();
+
+        let script = script.display();
+        let mut cmd = Command::new(shell_path);
+        match shell {
+            "bash" => cmd.args(["--norc", "--noprofile", "-c"]),
+            "fish" => cmd.args(["--no-config", "-c"]),
+            "tcsh" | "csh" => cmd.args(["-f", "-c"]),
+            "nu" => cmd.args(["--no-config-file", "-c"]),
+            "elvish" => cmd.args(["-norc", "-c"]),
+            "xonsh" => cmd.args(["--no-rc", "-c"]),
+            _ => cmd.arg("-c"),
+        };
+        match shell {
+            "fish" | "tcsh" | "csh" | "xonsh" => {
+                cmd.arg(format!("source '{}'; /usr/bin/env -0", script))
+            }
+            "nu" => cmd.arg(format!("source '{}'; ^/usr/bin/env -0", script)),
+            "elvish" => cmd.arg(format!("eval (slurp < '{}'); /usr/bin/env -0", script)),
+            _ => cmd.arg(format!(". '{}'; /usr/bin/env -0", script)),
+        };
+        let output = cmd.current_dir(dir.path()).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{} failed: {}",
+            shell,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(!dir.path().join("pwned").exists(), "{} ran injected code", shell);
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        Some(
+            stdout
+                .split('\0')
+                .filter_map(|entry| entry.split_once('='))
+                .filter(|(key, _)| env.contains_key(*key))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn scripts_round_trip_through_real_shells() {
+        let env = hostile_env();
+        for (shell, format) in [
+            ("sh", "sh"),
+            ("dash", "sh"),
+            ("bash", "bash"),
+            ("zsh", "zsh"),
+            ("fish", "fish"),
+            ("tcsh", "csh"),
+            ("csh", "csh"),
+            ("nu", "nu"),
+            ("elvish", "elvish"),
+            ("xonsh", "xonsh"),
+        ] {
+            if let Some(read_back) = round_trip(shell, format, &env) {
+                assert_eq!(read_back, env, "{} did not round-trip", shell);
+            }
+        }
+    }
 }
@@ -81,9 +81,74 @@ fn anvil(config: &str) -> Command {
     let mut cmd = Command::cargo_bin("anvil").unwrap();
     cmd.env("ANVIL_CONFIG", config);
     cmd.env("RUST_LOG", "anvil=error"); // suppress info logs in tests
+    // Keep each test's scan cache inside its own temp dir.
+    let root = std::path::Path::new(config).parent().unwrap();
+    cmd.env("ANVIL_CACHE_DIR", root.join("cache"));
     cmd
 }
 
+// ---- anvil activate / deactivate ----
+
+#[cfg(unix)]
+#[test]
+fn activate_and_deactivate_restore_previous_values() {
+    let (dir, cfg) = setup_env();
+    let mut config = fs::read_to_string(&cfg).unwrap();
+    config.push_str("inherit:\n  deny: [DROPPED]\n");
+    fs::write(&cfg, config).unwrap();
+
+    let script = r#"
+        export PYTHON_VERSION=old DROPPED=yes
+        unset MAYA_VERSION
+        before="$PATH"
+        eval "$("$ANVIL" activate maya-2024 --shell bash)"
+        echo "A:$MAYA_VERSION:$PYTHON_VERSION:${DROPPED-unset}:$ANVIL_ACTIVE"
+        eval "$("$ANVIL" activate python-3.11 --shell bash)"
+        echo "B:${MAYA_VERSION-unset}:$PYTHON_VERSION:$ANVIL_ACTIVE"
+        eval "$("$ANVIL" deactivate --shell bash)"
+        echo "D:${MAYA_VERSION-unset}:$PYTHON_VERSION:$DROPPED:${ANVIL_ACTIVE-unset}:${ANVIL_ACTIVE_STATE-unset}"
+        [ "$PATH" = "$before" ] && echo "PATH restored"
+    "#;
+    let output = std::process::Command::new("bash")
+        .args(["-c", script])
+        .env("ANVIL", assert_cmd::cargo::cargo_bin("anvil"))
+        .env("ANVIL_CONFIG", &cfg)
+        .env("ANVIL_CACHE_DIR", dir.path().join("cache"))
+        .env("RUST_LOG", "anvil=error")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("A:2024:3.11:unset:maya-2024"), "{}", stdout);
+    assert!(stdout.contains("B:unset:3.11:python-3.11"), "{}", stdout);
+    assert!(stdout.contains("D:unset:old:yes:unset:unset"), "{}", stdout);
+    assert!(stdout.contains("PATH restored"), "{}", stdout);
+}
+
+#[test]
+fn deactivate_without_activation_fails() {
+    let (_dir, cfg) = setup_env();
+    anvil(&cfg)
+        .env_remove("ANVIL_ACTIVE_STATE")
+        .args(["deactivate", "--shell", "bash"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No anvil environment is active"));
+
+    anvil(&cfg)
+        .args(["activate", "maya-2024", "--shell", "dotenv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Can't activate in dotenv"));
+
+    anvil(&cfg)
+        .args(["activate", "maya-2024", "--shell", "fish"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("set -gx MAYA_VERSION '2024'"))
+        .stdout(predicate::str::contains("set -gx ANVIL_ACTIVE_STATE"));
+}
+
 // ---- anvil list ----
 
 #[test]
@@ -166,6 +231,78 @@ fn env_export() {
         .stdout(predicate::str::contains("export MAYA_VERSION=\"2024\""));
 }
 
+#[test]
+fn env_formats() {
+    let (dir, cfg) = setup_env();
+    let quoted = dir.path().join("packages/quoted/1.0");
+    fs::create_dir_all(&quoted).unwrap();
+    fs::write(
+        quoted.join("package.yaml"),
+        "name: quoted\nversion: \"1.0\"\nenvironment:\n  GREETING: 'say \"hi\" for 5%'\n",
+    )
+    .unwrap();
+
+    let cases = [
+        ("dotenv", r#"GREETING="say \"hi\" for 5%""#),
+        ("dockerfile", r#"ENV GREETING="say \"hi\" for 5%""#),
+        ("systemd", r#"Environment="GREETING=say \"hi\" for 5%%""#),
+        ("github-actions", r#"GREETING=say "hi" for 5%"#),
+        ("powershell", r#"$env:GREETING = 'say "hi" for 5%'"#),
+        ("fish", r#"set -gx GREETING 'say "hi" for 5%'"#),
+        ("csh", r#"setenv GREETING 'say "hi" for 5%'"#),
+    ];
+    for (format, line) in cases {
+        anvil(&cfg)
+            .args(["env", "quoted", "--pure", "--format", format])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(line));
+    }
+
+    // Files for other tools get only what the packages set.
+    for format in ["dotenv", "dockerfile", "systemd", "github-actions"] {
+        anvil(&cfg)
+            .env("ANVIL_TEST_CALLER", "leak")
+            .args(["env", "quoted", "--format", format])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("GREETING"))
+            .stdout(predicate::str::contains("ANVIL_TEST_CALLER").not());
+    }
+    anvil(&cfg)
+        .env("ANVIL_TEST_CALLER", "leak")
+        .args(["env", "quoted", "--format", "sh"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ANVIL_TEST_CALLER"));
+
+    // `--export` escapes quotes too.
+    anvil(&cfg)
+        .args(["env", "quoted", "--export"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#"export GREETING="say \"hi\" for 5%""#));
+
+    anvil(&cfg)
+        .args(["env", "quoted", "--format", "yaml"])
+        .assert()
+        .failure();
+
+    // `context show` shares the formats.
+    let ctx_path = dir.path().join("quoted.ctx.json");
+    anvil(&cfg)
+        .args(["context", "save", "quoted", "-o"])
+        .arg(&ctx_path)
+        .assert()
+        .success();
+    anvil(&cfg)
+        .args(["context", "show", "--format", "dotenv"])
+        .arg(&ctx_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("GREETING=\"say \\\"hi\\\" for 5%\"\n"));
+}
+
 // ---- anvil validate ----
 
 #[test]
@@ -414,6 +551,216 @@ fn lock_pins_versions() {
         .stdout(predicate::str::contains("MAYA_VERSION=2024"));
 }
 
+#[test]
+fn lock_check_passes_then_catches_edits() {
+    let (dir, cfg) = setup_env();
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "maya-2024"])
+        .assert()
+        .success();
+    let content = fs::read_to_string(dir.path().join("anvil.lock")).unwrap();
+    assert!(content.contains("sha256"));
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--check"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("is up to date (2 packages)"));
+
+    // Republish maya-2024 in place.
+    let maya = dir.path().join("packages/maya-2024.yaml");
+    let yaml = fs::read_to_string(&maya).unwrap();
+    fs::write(&maya, yaml.replace("environment:\n", "environment:\n  PATCHED: \"1\"\n"))
+        .unwrap();
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--check"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("maya-2024.yaml changed since it was locked"));
+}
+
+#[test]
+fn lock_check_catches_request_drift_and_missing_hashes() {
+    let (dir, cfg) = setup_env();
+    fs::write(
+        dir.path().join("anvil.lock"),
+        "requests: [maya-2024]\npins:\n  maya: \"2024\"\n",
+    )
+    .unwrap();
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--check", "maya-2024", "studio-blender-tools"])
+        .assert()
+        .failure()
+        .stderr(
+            predicate::str::contains("requests changed")
+                .and(predicate::str::contains("python-3.11: not pinned"))
+                .and(predicate::str::contains("maya-2024: no integrity hash recorded")),
+        );
+}
+
+#[test]
+fn lock_update_bumps_only_named_packages() {
+    let (dir, cfg) = setup_env();
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "python", "studio-blender-tools"])
+        .assert()
+        .success();
+
+    for (name, version) in [("python", "3.12"), ("studio-blender-tools", "2.0.0")] {
+        let pkg = dir.path().join("packages").join(name).join(version);
+        fs::create_dir_all(&pkg).unwrap();
+        fs::write(
+            pkg.join("package.yaml"),
+            format!("name: {}\nversion: \"{}\"\n", name, version),
+        )
+        .unwrap();
+    }
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--update", "python"])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("python  3.11 -> 3.12")
+                .and(predicate::str::contains("studio-blender-tools").not()),
+        );
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--update"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("studio-blender-tools  1.0.0 -> 2.0.0"));
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--update"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already up to date"));
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--update", "nuke"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("nuke is not pinned"));
+}
+
+#[test]
+fn lock_multiple_platforms() {
+    let (dir, cfg) = setup_env();
+    let tool = dir.path().join("packages/tool/1.0");
+    fs::create_dir_all(&tool).unwrap();
+    fs::write(
+        tool.join("package.yaml"),
+        r#"
+name: tool
+version: "1.0"
+variants:
+  - platform: windows
+    requires: [python-3.11]
+"#,
+    )
+    .unwrap();
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "tool", "--platforms", "linux,windows"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Locked anvil.lock for linux, windows"));
+
+    let content = fs::read_to_string(dir.path().join("anvil.lock")).unwrap();
+    let lock: serde_yaml::Value = serde_yaml::from_str(&content).unwrap();
+    let platforms = &lock["platforms"];
+    assert!(platforms["linux"]["pins"]["python"].is_null());
+    assert_eq!(platforms["windows"]["pins"]["python"].as_str(), Some("3.11"));
+    assert_eq!(platforms["windows"]["pins"]["tool"].as_str(), Some("1.0"));
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--check"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 on linux, 2 on windows"));
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "--platforms", "beos", "tool"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn resolve_for_another_platform() {
+    let (dir, cfg) = setup_env();
+    let tool = dir.path().join("packages/tool/1.0");
+    fs::create_dir_all(&tool).unwrap();
+    fs::write(
+        tool.join("package.yaml"),
+        r#"
+name: tool
+version: "1.0"
+environment:
+  TOOL_PATH: /opt/a${PATHSEP}/opt/b
+  TOOL_BIN: tool${EXE_SUFFIX}
+variants:
+  - platform: windows
+    requires: [python-3.11]
+    environment:
+      TOOL_OS: win
+"#,
+    )
+    .unwrap();
+
+    anvil(&cfg)
+        .args(["env", "tool", "--platform", "windows"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("TOOL_PATH=/opt/a;/opt/b"))
+        .stdout(predicate::str::contains("TOOL_BIN=tool.exe"))
+        .stdout(predicate::str::contains("TOOL_OS=win"))
+        .stdout(predicate::str::contains("PYTHON_VERSION=3.11"));
+
+    let ctx_path = dir.path().join("win.ctx.json");
+    anvil(&cfg)
+        .args(["context", "save", "tool", "--platform", "windows-x86_64", "-o"])
+        .arg(&ctx_path)
+        .assert()
+        .success();
+    let ctx: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&ctx_path).unwrap()).unwrap();
+    assert_eq!(ctx["platform"].as_str(), Some("windows"));
+    let ops = ctx["operations"].as_array().unwrap();
+    assert!(ops
+        .iter()
+        .any(|op| op["name"] == "TOOL_BIN" && op["value"] == "tool.exe"));
+
+    anvil(&cfg)
+        .current_dir(dir.path())
+        .args(["lock", "tool", "--platform", "windows"])
+        .assert()
+        .success();
+    let lock: serde_yaml::Value =
+        serde_yaml::from_str(&fs::read_to_string(dir.path().join("anvil.lock")).unwrap()).unwrap();
+    assert_eq!(lock["platforms"]["windows"]["pins"]["python"].as_str(), Some("3.11"));
+
+    anvil(&cfg)
+        .args(["env", "tool", "--platform", "beos"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown platform"));
+}
+
 // ---- anvil context ----
 
 #[test]
@@ -473,74 +820,408 @@ fn context_show_export() {
         .stdout(predicate::str::contains("export MAYA_VERSION=\"2024\""));
 }
 
-// ---- anvil init ----
-
 #[test]
-fn init_nested() {
-    let dir = TempDir::new().unwrap();
+fn context_stores_package_operations_only() {
+    let (dir, cfg) = setup_env();
+    let ctx_path = dir.path().join("portable.ctx.json");
 
-    Command::cargo_bin("anvil")
+    anvil(&cfg)
+        .env("ARTIST_ONLY", "secret")
+        .args(["context", "save", "maya-2024", "-o"])
+        .arg(&ctx_path)
+        .assert()
+        .success();
+    let content = fs::read_to_string(&ctx_path).unwrap();
+    assert!(!content.contains("ARTIST_ONLY"));
+    let ctx: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert!(ctx["environment"].is_null());
+    assert!(ctx["base"].is_null());
+    assert!(ctx["operations"]
+        .as_array()
         .unwrap()
-        .env("RUST_LOG", "anvil=error")
-        .current_dir(dir.path())
-        .args(["init", "my-tool", "--version", "2.0"])
+        .iter()
+        .any(|op| op["name"] == "MAYA_VERSION" && op["package"] == "maya-2024"));
+
+    // Replayed on top of the loading machine's environment.
+    anvil(&cfg)
+        .env("FARM_NODE", "node42")
+        .args(["context", "run"])
+        .arg(&ctx_path)
+        .args(["--", "sh", "-c", "echo $FARM_NODE $MAYA_VERSION ${ARTIST_ONLY:-unset}"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("Created my-tool/2.0/package.yaml"));
+        .stdout(predicate::str::contains("node42 2024 unset"));
 
-    let pkg = dir.path().join("my-tool/2.0/package.yaml");
-    assert!(pkg.exists());
-    let content = fs::read_to_string(pkg).unwrap();
-    assert!(content.contains("name: my-tool"));
-    assert!(content.contains("version: \"2.0\""));
+    anvil(&cfg)
+        .args(["context", "run", "--captured-base"])
+        .arg(&ctx_path)
+        .args(["--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no captured base"));
+
+    // A captured base reproduces the saving environment.
+    anvil(&cfg)
+        .env("ARTIST_ONLY", "secret")
+        .args(["context", "save", "maya-2024", "--capture-base", "-o"])
+        .arg(&ctx_path)
+        .assert()
+        .success();
+    anvil(&cfg)
+        .env("FARM_NODE", "node42")
+        .args(["context", "run", "--captured-base"])
+        .arg(&ctx_path)
+        .args(["--", "sh", "-c", "echo ${FARM_NODE:-unset} $ARTIST_ONLY"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("unset secret"));
 }
 
+#[cfg(unix)]
 #[test]
-fn init_flat() {
-    let dir = TempDir::new().unwrap();
+fn context_run_and_shell_resolve_command_aliases() {
+    use std::os::unix::fs::PermissionsExt;
 
-    Command::cargo_bin("anvil")
-        .unwrap()
-        .env("RUST_LOG", "anvil=error")
-        .current_dir(dir.path())
-        .args(["init", "quick-fix", "--flat"])
+    let (dir, cfg) = setup_env();
+    let tool = dir.path().join("packages/greeter/1.0");
+    fs::create_dir_all(tool.join("bin")).unwrap();
+    let script = tool.join("bin/greet");
+    fs::write(&script, "#!/bin/sh\necho \"greet:$*\"\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        tool.join("package.yaml"),
+        r#"
+name: greeter
+version: "1.0"
+commands:
+  hello: ${PACKAGE_ROOT}/bin/greet --loud
+"#,
+    )
+    .unwrap();
+
+    let ctx_path = dir.path().join("greeter.ctx.json");
+    anvil(&cfg)
+        .args(["context", "save", "greeter", "-o"])
+        .arg(&ctx_path)
+        .assert()
+        .success();
+
+    anvil(&cfg)
+        .args(["context", "run"])
+        .arg(&ctx_path)
+        .args(["--", "hello", "world"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("Created quick-fix-1.0.0.yaml"));
+        .stdout(predicate::str::contains("greet:--loud world"));
 
-    let pkg = dir.path().join("quick-fix-1.0.0.yaml");
-    assert!(pkg.exists());
-    let content = fs::read_to_string(pkg).unwrap();
-    assert!(content.contains("name: quick-fix"));
+    anvil(&cfg)
+        .args(["context", "shell", "-s", "sh"])
+        .arg(&ctx_path)
+        .write_stdin("hello from-shim\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("greet:--loud from-shim"));
 }
 
 #[test]
-fn init_refuses_overwrite() {
-    let dir = TempDir::new().unwrap();
+fn path_mappings_translate_across_platforms() {
+    let (dir, cfg) = setup_env();
+    let pkg_dir = dir.path().join("packages");
+    let mut config = fs::read_to_string(&cfg).unwrap();
+    config.push_str(&format!(
+        "path_mappings:\n  - linux: {}\n    macos: {}\n    windows: \"S:/pkgs\"\n",
+        pkg_dir.display(),
+        pkg_dir.display()
+    ));
+    fs::write(&cfg, config).unwrap();
 
-    // Create first
-    Command::cargo_bin("anvil")
-        .unwrap()
-        .env("RUST_LOG", "anvil=error")
-        .current_dir(dir.path())
-        .args(["init", "my-tool"])
+    anvil(&cfg)
+        .args(["env", "python-3.11", "--platform", "windows"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PATH=S:/pkgs/python/3.11/bin"));
+
+    let ctx_path = dir.path().join("py.ctx.json");
+    anvil(&cfg)
+        .args(["context", "save", "python-3.11", "-o"])
+        .arg(&ctx_path)
         .assert()
         .success();
+    anvil(&cfg)
+        .args(["context", "remap", "--to", "windows"])
+        .arg(&ctx_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("py.ctx.windows.json"));
+
+    let win_path = dir.path().join("py.ctx.windows.json");
+    let ctx: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&win_path).unwrap()).unwrap();
+    assert_eq!(ctx["platform"].as_str(), Some("windows"));
+    let ops = ctx["operations"].as_array().unwrap();
+    assert!(ops
+        .iter()
+        .any(|op| op["name"] == "PATH" && op["value"] == "S:/pkgs/python/3.11/bin;${PATH}"));
+    assert_eq!(
+        ctx["commands"]["python"].as_str(),
+        Some("S:/pkgs/python/3.11/bin/python3.11")
+    );
+
+    // Loading the Windows copy here maps the paths back.
+    let root = pkg_dir.join("python/3.11").display().to_string();
+    anvil(&cfg)
+        .args(["context", "show", "--json"])
+        .arg(&win_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{}/bin", root)));
 
-    // Second attempt should fail
-    Command::cargo_bin("anvil")
-        .unwrap()
-        .env("RUST_LOG", "anvil=error")
-        .current_dir(dir.path())
-        .args(["init", "my-tool"])
+    anvil(&cfg)
+        .args(["config", "show"])
         .assert()
-        .failure();
+        .success()
+        .stdout(predicate::str::contains("windows=S:/pkgs"));
 }
 
-// ---- alias with spaces in path ----
-
 #[test]
-fn run_whole_path_with_spaces() {
+fn changing_path_mappings_invalidates_cached_resolves() {
+    let (dir, cfg) = setup_env();
+    let pkg_dir = dir.path().join("packages");
+    let base = fs::read_to_string(&cfg).unwrap();
+    let with_mapping = |windows: &str| {
+        format!(
+            "{}path_mappings:\n  - linux: {}\n    macos: {}\n    windows: \"{}\"\n",
+            base,
+            pkg_dir.display(),
+            pkg_dir.display(),
+            windows
+        )
+    };
+
+    fs::write(&cfg, with_mapping("S:/pkgs")).unwrap();
+    anvil(&cfg)
+        .args(["env", "python-3.11", "--platform", "windows"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PATH=S:/pkgs/python/3.11/bin"));
+
+    fs::write(&cfg, with_mapping("T:/pkgs")).unwrap();
+    anvil(&cfg)
+        .args(["env", "python-3.11", "--platform", "windows"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PATH=T:/pkgs/python/3.11/bin"))
+        .stdout(predicate::str::contains("S:/pkgs").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn context_verify_exit_codes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, cfg) = setup_env();
+    let tool = dir.path().join("packages/greeter/1.0");
+    fs::create_dir_all(tool.join("bin")).unwrap();
+    let script = tool.join("bin/greet");
+    fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        tool.join("package.yaml"),
+        "name: greeter\nversion: \"1.0\"\ncommands:\n  hello: ${PACKAGE_ROOT}/bin/greet\n",
+    )
+    .unwrap();
+
+    let ctx_path = dir.path().join("greeter.ctx.json");
+    anvil(&cfg)
+        .args(["context", "save", "greeter", "-o"])
+        .arg(&ctx_path)
+        .assert()
+        .success();
+
+    let verify = |extra: &[&str]| {
+        let mut cmd = anvil(&cfg);
+        cmd.args(["context", "verify"]).arg(&ctx_path).args(extra);
+        cmd.assert()
+    };
+
+    verify(&[]).success().stdout(predicate::str::contains("is usable"));
+
+    // A newer release only matters when re-resolving.
+    let newer = dir.path().join("packages/greeter/2.0");
+    fs::create_dir_all(&newer).unwrap();
+    fs::write(newer.join("package.yaml"), "name: greeter\nversion: \"2.0\"\n").unwrap();
+    verify(&[]).success();
+    verify(&["--resolve"])
+        .code(16)
+        .stderr(predicate::str::contains("greeter now resolves to 2.0"));
+
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o644)).unwrap();
+    verify(&[]).code(4).stderr(predicate::str::contains("[command] hello"));
+
+    fs::remove_dir_all(&tool).unwrap();
+    verify(&[]).code(2 | 4).stderr(predicate::str::contains("[missing root] greeter-1.0"));
+
+    // A package removed altogether is stale too, not a hard failure.
+    fs::remove_dir_all(&newer).unwrap();
+    verify(&["--resolve"])
+        .code(2 | 4 | 16)
+        .stderr(predicate::str::contains("greeter no longer resolves"));
+
+    let content = fs::read_to_string(&ctx_path).unwrap();
+    let mut ctx: serde_json::Value = serde_json::from_str(&content).unwrap();
+    ctx["platform"] = serde_json::json!("beos");
+    fs::write(&ctx_path, ctx.to_string()).unwrap();
+    verify(&[]).code(2 | 4 | 8).stderr(predicate::str::contains("resolved for beos"));
+}
+
+fn write_context(path: &std::path::Path, packages: &[(&str, &str)], env: &[(&str, &str)]) {
+    let ctx = serde_json::json!({
+        "anvil_version": "0.0.0",
+        "created": 0,
+        "platform": "linux",
+        "requests": ["maya-2024"],
+        "resolved": packages
+            .iter()
+            .map(|(name, version)| serde_json::json!({"name": name, "version": version}))
+            .collect::<Vec<_>>(),
+        "environment": env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<std::collections::HashMap<_, _>>(),
+    });
+    fs::write(path, serde_json::to_string_pretty(&ctx).unwrap()).unwrap();
+}
+
+#[test]
+fn context_diff() {
+    let (dir, cfg) = setup_env();
+    let a = dir.path().join("artist.ctx.json");
+    let b = dir.path().join("farm.ctx.json");
+    write_context(
+        &a,
+        &[("maya", "2024"), ("python", "3.10"), ("mtoa", "5.3")],
+        &[
+            ("PATH", "/opt/maya/bin:/usr/bin:/home/artist/bin"),
+            ("MAYA_VERSION", "2024"),
+            ("EDITOR", "vim"),
+        ],
+    );
+    write_context(
+        &b,
+        &[("maya", "2024"), ("python", "3.11"), ("usd", "24.08")],
+        &[
+            ("PATH", "/usr/bin:/opt/maya/bin:/opt/usd/bin"),
+            ("MAYA_VERSION", "2024.2"),
+            ("RENDER_NODE", "1"),
+        ],
+    );
+
+    anvil(&cfg)
+        .args(["context", "diff"])
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("+ usd-24.08"))
+        .stdout(predicate::str::contains("- mtoa-5.3"))
+        .stdout(predicate::str::contains("~ python  3.10 -> 3.11"))
+        .stdout(predicate::str::contains("+ RENDER_NODE=1"))
+        .stdout(predicate::str::contains("- EDITOR=vim"))
+        .stdout(predicate::str::contains("~ MAYA_VERSION: 2024 -> 2024.2"))
+        .stdout(predicate::str::contains("      + /opt/usd/bin"))
+        .stdout(predicate::str::contains("      - /home/artist/bin"))
+        .stdout(predicate::str::contains("(order changed)"));
+
+    let output = anvil(&cfg)
+        .args(["context", "diff", "--json"])
+        .arg(&a)
+        .arg(&b)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(diff["packages"]["changed"]["python"], serde_json::json!(["3.10", "3.11"]));
+    assert_eq!(diff["packages"]["added"]["usd"].as_str(), Some("24.08"));
+    let path = &diff["environment"]["changed"]["PATH"]["segments"];
+    assert_eq!(path["added"], serde_json::json!(["/opt/usd/bin"]));
+    assert_eq!(path["removed"], serde_json::json!(["/home/artist/bin"]));
+    assert_eq!(path["reordered"].as_bool(), Some(true));
+    assert!(diff["environment"]["changed"]["MAYA_VERSION"]["segments"].is_null());
+
+    anvil(&cfg)
+        .args(["context", "diff"])
+        .arg(&a)
+        .arg(&a)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("are identical"));
+}
+
+// ---- anvil init ----
+
+#[test]
+fn init_nested() {
+    let dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("anvil")
+        .unwrap()
+        .env("RUST_LOG", "anvil=error")
+        .current_dir(dir.path())
+        .args(["init", "my-tool", "--version", "2.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created my-tool/2.0/package.yaml"));
+
+    let pkg = dir.path().join("my-tool/2.0/package.yaml");
+    assert!(pkg.exists());
+    let content = fs::read_to_string(pkg).unwrap();
+    assert!(content.contains("name: my-tool"));
+    assert!(content.contains("version: \"2.0\""));
+}
+
+#[test]
+fn init_flat() {
+    let dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("anvil")
+        .unwrap()
+        .env("RUST_LOG", "anvil=error")
+        .current_dir(dir.path())
+        .args(["init", "quick-fix", "--flat"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created quick-fix-1.0.0.yaml"));
+
+    let pkg = dir.path().join("quick-fix-1.0.0.yaml");
+    assert!(pkg.exists());
+    let content = fs::read_to_string(pkg).unwrap();
+    assert!(content.contains("name: quick-fix"));
+}
+
+#[test]
+fn init_refuses_overwrite() {
+    let dir = TempDir::new().unwrap();
+
+    // Create first
+    Command::cargo_bin("anvil")
+        .unwrap()
+        .env("RUST_LOG", "anvil=error")
+        .current_dir(dir.path())
+        .args(["init", "my-tool"])
+        .assert()
+        .success();
+
+    // Second attempt should fail
+    Command::cargo_bin("anvil")
+        .unwrap()
+        .env("RUST_LOG", "anvil=error")
+        .current_dir(dir.path())
+        .args(["init", "my-tool"])
+        .assert()
+        .failure();
+}
+
+// ---- alias with spaces in path ----
+
+#[test]
+fn run_whole_path_with_spaces() {
     // When the alias value is a bare path to an existing file containing
     // spaces, it should be executed directly without being split on
     // whitespace. Regression for `/Applications/Houdini 20/bin/hython`.
@@ -709,6 +1390,26 @@ fn filter_exclude() {
         .stdout(predicate::str::contains("studio-blender-tools").not());
 }
 
+#[test]
+fn filter_edit_reuses_cached_scan() {
+    let (_dir, cfg) = setup_env();
+    anvil(&cfg).args(["list"]).assert().success();
+
+    // Filters apply after the scan, so the cached entry stays valid.
+    let mut content = fs::read_to_string(&cfg).unwrap();
+    content.push_str("filters:\n  exclude:\n    - \"maya*\"\n");
+    fs::write(&cfg, content).unwrap();
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=info")
+        .args(["list"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Using cached package scan"))
+        .stderr(predicate::str::contains("Re-read").not())
+        .stdout(predicate::str::contains("python").and(predicate::str::contains("maya").not()));
+}
+
 // ---- hooks ----
 
 #[test]
@@ -803,6 +1504,29 @@ fn wrap_includes_dependency_commands() {
     assert!(wrap_dir.join("python").exists());
 }
 
+#[test]
+fn wrap_writes_for_each_shell() {
+    let (dir, cfg) = setup_env();
+
+    for (shell, shebang, args) in [
+        ("tcsh", "#!/bin/csh -f", "$argv:q"),
+        ("nu", "#!/usr/bin/env nu", "...$args"),
+        ("elvish", "#!/usr/bin/env elvish", "$@args"),
+        ("xonsh", "#!/usr/bin/env xonsh", "@($ARGS[1:])"),
+    ] {
+        let wrap_dir = dir.path().join(shell);
+        anvil(&cfg)
+            .args(["wrap", "maya-2024", "--shell", shell, "--dir", wrap_dir.to_str().unwrap()])
+            .assert()
+            .success();
+
+        let content = fs::read_to_string(wrap_dir.join("maya")).unwrap();
+        assert!(content.starts_with(shebang), "{}: {}", shell, content);
+        assert!(content.contains("-- maya"), "{}: {}", shell, content);
+        assert!(content.contains(args), "{}: {}", shell, content);
+    }
+}
+
 // ---- anvil publish ----
 
 #[test]
@@ -1055,3 +1779,619 @@ fn shell_help_exposes_shim_flags() {
         .stdout(predicate::str::contains("--env-only"))
         .stdout(predicate::str::contains("--no-sweep"));
 }
+
+// ---- anvil config ----
+
+#[test]
+fn config_show_annotates_origin() {
+    let (_dir, cfg) = setup_env();
+    anvil(&cfg)
+        .args(["config", "show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("maya-full: [maya-2024]"))
+        .stdout(predicate::str::contains(format!("global ({})", cfg)));
+}
+
+#[test]
+fn config_show_json_tracks_project_layer() {
+    let dir = TempDir::new().unwrap();
+    let project_dir = dir.path().join("myproject");
+    fs::create_dir_all(&project_dir).unwrap();
+    fs::write(
+        project_dir.join(".anvil.yaml"),
+        "package_paths: [/project/packages]\ndefault_shell: zsh\n",
+    )
+    .unwrap();
+    let global_cfg = dir.path().join("global.yaml");
+    fs::write(&global_cfg, "package_paths: [/global/packages]\n").unwrap();
+
+    let output = Command::cargo_bin("anvil")
+        .unwrap()
+        .env("ANVIL_CONFIG", global_cfg.to_str().unwrap())
+        .env("RUST_LOG", "anvil=error")
+        .current_dir(&project_dir)
+        .args(["config", "show", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let v: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let paths = v["package_paths"].as_array().unwrap();
+    assert_eq!(paths[0]["value"], "/project/packages");
+    assert_eq!(paths[0]["origin"]["layer"], "project");
+    assert_eq!(paths[1]["value"], "/global/packages");
+    assert_eq!(paths[1]["origin"]["layer"], "global");
+    assert_eq!(v["default_shell"]["origin"]["layer"], "project");
+    assert_eq!(v["filters"]["origin"]["layer"], "default");
+}
+
+#[test]
+fn config_path_lists_consulted_files() {
+    let dir = TempDir::new().unwrap();
+    let missing = dir.path().join("nope.yaml");
+    Command::cargo_bin("anvil")
+        .unwrap()
+        .env("ANVIL_CONFIG", missing.to_str().unwrap())
+        .env("RUST_LOG", "anvil=error")
+        .current_dir(dir.path())
+        .args(["config", "path"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nope.yaml (global, not found)"))
+        .stdout(predicate::str::contains(
+            ".anvil.yaml (project, not found here or in any parent directory)",
+        ));
+}
+
+// ---- anvil alias ----
+
+fn setup_alias_env() -> (TempDir, String) {
+    let (dir, _) = setup_env();
+    let pkg_dir = dir.path().join("packages");
+    fs::write(
+        pkg_dir.join("maya-2025.yaml"),
+        "name: maya\nversion: \"2025\"\nenvironment:\n  MAYA_VERSION: \"2025\"\n",
+    )
+    .unwrap();
+    let cfg_path = dir.path().join("aliases.yaml");
+    fs::write(
+        &cfg_path,
+        format!(
+            "package_paths:\n  - {}\naliases:\n  studio-base: [studio-blender-tools]\n  studio-maya: [\"maya-${{ARG:-2024}}\", studio-base]\n",
+            pkg_dir.display()
+        ),
+    )
+    .unwrap();
+    let cfg = cfg_path.to_string_lossy().to_string();
+    (dir, cfg)
+}
+
+#[test]
+fn alias_nested_with_parameter_resolves() {
+    let (_dir, cfg) = setup_alias_env();
+    anvil(&cfg)
+        .args(["env", "studio-maya@2025"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MAYA_VERSION=2025"))
+        .stdout(predicate::str::contains("STUDIO_TOOLS=enabled"));
+
+    anvil(&cfg)
+        .args(["env", "studio-maya"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MAYA_VERSION=2024"));
+}
+
+#[test]
+fn alias_show_prints_expansion() {
+    let (_dir, cfg) = setup_alias_env();
+    anvil(&cfg)
+        .args(["alias", "show", "studio-maya@2025"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("maya-${ARG:-2024}"))
+        .stdout(predicate::str::contains("  - maya-2025\n  - studio-blender-tools"));
+
+    anvil(&cfg)
+        .args(["alias", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("studio-base: studio-blender-tools"));
+}
+
+#[test]
+fn filter_exclude_by_version() {
+    let (dir, _) = setup_env();
+    let pkg_dir = dir.path().join("packages");
+    fs::write(
+        pkg_dir.join("maya-2023.yaml"),
+        "name: maya\nversion: \"2023\"\n",
+    )
+    .unwrap();
+    fs::write(
+        pkg_dir.join("maya-2025beta1.yaml"),
+        "name: maya\nversion: \"2025beta1\"\n",
+    )
+    .unwrap();
+    let cfg_path = dir.path().join("filtered.yaml");
+    fs::write(
+        &cfg_path,
+        format!(
+            "package_paths:\n  - {}\nfilters:\n  exclude:\n    - \"maya-<2024\"\n    - \"*-*beta*\"\n",
+            pkg_dir.display()
+        ),
+    )
+    .unwrap();
+    let cfg = cfg_path.to_str().unwrap();
+
+    anvil(cfg)
+        .args(["list", "maya"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2024"))
+        .stdout(predicate::str::contains("2023").not())
+        .stdout(predicate::str::contains("beta").not());
+
+    anvil(cfg)
+        .args(["list", "--hidden"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("maya-2023  (exclude \"maya-<2024\")"))
+        .stdout(predicate::str::contains("maya-2025beta1  (exclude \"*-*beta*\")"));
+}
+
+// ---- config overrides ----
+
+#[test]
+fn override_patches_package_environment() {
+    let (dir, _) = setup_env();
+    let pkg_dir = dir.path().join("packages");
+    let cfg_path = dir.path().join("overrides.yaml");
+    fs::write(
+        &cfg_path,
+        format!(
+            "package_paths:\n  - {}\noverrides:\n  maya-2024:\n    environment:\n      MAYA_VERSION: show-2024\n      OCIO: /show/ocio/config.ocio\n    requires: [studio-blender-tools]\n",
+            pkg_dir.display()
+        ),
+    )
+    .unwrap();
+    let cfg = cfg_path.to_str().unwrap();
+
+    anvil(cfg)
+        .args(["env", "maya-2024"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MAYA_VERSION=show-2024"))
+        .stdout(predicate::str::contains("OCIO=/show/ocio/config.ocio"))
+        .stdout(predicate::str::contains("STUDIO_TOOLS=enabled"));
+
+    anvil(cfg)
+        .args(["info", "maya-2024"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Overrides (from config):"))
+        .stdout(predicate::str::contains("environment: OCIO=/show/ocio/config.ocio"));
+
+    // Other packages are untouched.
+    anvil(cfg)
+        .args(["env", "python-3.11"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("OCIO=").not());
+}
+
+// ---- inherited environment ----
+
+#[test]
+fn pure_env_drops_caller_variables() {
+    let (_dir, cfg) = setup_env();
+    anvil(&cfg)
+        .env("ANVIL_TEST_LEAK", "stale")
+        .env("HOME", "/home/anvil-test")
+        .args(["env", "--pure", "maya-2024"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MAYA_VERSION=2024"))
+        .stdout(predicate::str::contains("HOME=/home/anvil-test"))
+        .stdout(predicate::str::contains("ANVIL_TEST_LEAK").not());
+}
+
+#[test]
+fn inherit_deny_and_allow() {
+    let (dir, _) = setup_env();
+    let pkg_dir = dir.path().join("packages");
+    let cfg_path = dir.path().join("inherit.yaml");
+    fs::write(
+        &cfg_path,
+        format!(
+            "package_paths:\n  - {}\ninherit:\n  allow: [\"ANVIL_KEEP_*\"]\n  deny: [ANVIL_TEST_LEAK]\n",
+            pkg_dir.display()
+        ),
+    )
+    .unwrap();
+    let cfg = cfg_path.to_str().unwrap();
+
+    anvil(cfg)
+        .env("ANVIL_TEST_LEAK", "stale")
+        .env("ANVIL_OTHER", "inherited")
+        .args(["env", "maya-2024"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ANVIL_OTHER=inherited"))
+        .stdout(predicate::str::contains("ANVIL_TEST_LEAK").not());
+
+    anvil(cfg)
+        .env("ANVIL_KEEP_ME", "yes")
+        .env("ANVIL_OTHER", "inherited")
+        .args(["env", "--pure", "maya-2024"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ANVIL_KEEP_ME=yes"))
+        .stdout(predicate::str::contains("ANVIL_OTHER").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn pure_run_clears_child_environment() {
+    let (_dir, cfg) = setup_env();
+    anvil(&cfg)
+        .env("ANVIL_TEST_LEAK", "stale")
+        .args(["run", "--pure", "maya-2024", "--", "/usr/bin/env"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MAYA_VERSION=2024"))
+        .stdout(predicate::str::contains("ANVIL_TEST_LEAK").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn pure_context_replays_on_a_pure_base() {
+    let (dir, cfg) = setup_env();
+    let pure = dir.path().join("pure.ctx.json");
+    let inheriting = dir.path().join("inheriting.ctx.json");
+    for (path, extra) in [(&pure, Some("--pure")), (&inheriting, None)] {
+        anvil(&cfg)
+            .args(["context", "save", "maya-2024", "-o"])
+            .arg(path)
+            .args(extra)
+            .assert()
+            .success();
+    }
+    let ctx: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&pure).unwrap()).unwrap();
+    assert_eq!(ctx["inherit"]["pure"], true);
+
+    anvil(&cfg)
+        .env("ANVIL_TEST_LEAK", "stale")
+        .args(["context", "run"])
+        .arg(&pure)
+        .args(["--", "/usr/bin/env"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MAYA_VERSION=2024"))
+        .stdout(predicate::str::contains("ANVIL_TEST_LEAK").not());
+
+    anvil(&cfg)
+        .env("ANVIL_TEST_LEAK", "stale")
+        .args(["context", "run"])
+        .arg(&inheriting)
+        .args(["--", "/usr/bin/env"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ANVIL_TEST_LEAK=stale"));
+}
+
+// ---- lazy resolution ----
+
+#[test]
+fn resolve_skips_unrelated_packages() {
+    let (dir, cfg) = setup_env();
+    let broken = dir.path().join("packages/broken/1.0");
+    fs::create_dir_all(&broken).unwrap();
+    fs::write(broken.join("package.yaml"), "name: [not valid").unwrap();
+
+    // `env` only parses the families it reaches, so the broken one is never read.
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=warn")
+        .args(["--refresh", "env", "maya-2024"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Failed to load package").not());
+
+    // `list` still does a full scan.
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=warn")
+        .args(["--refresh", "list"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Failed to load package"));
+}
+
+#[test]
+fn lazy_resolve_falls_back_when_dir_name_differs() {
+    let (dir, cfg) = setup_env();
+    let odd = dir.path().join("packages/odd-dir/1.0");
+    fs::create_dir_all(&odd).unwrap();
+    fs::write(
+        odd.join("package.yaml"),
+        "name: oddname\nversion: \"1.0\"\nenvironment:\n  ODD: \"yes\"\n",
+    )
+    .unwrap();
+
+    anvil(&cfg)
+        .args(["env", "oddname"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ODD=yes"));
+}
+
+// ---- anvil cache ----
+
+#[test]
+fn cache_warm_info_clear() {
+    let (dir, cfg) = setup_env();
+    let cache_dir = dir.path().join("cache");
+
+    anvil(&cfg)
+        .args(["cache", "warm"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Warmed").and(predicate::str::contains("3 packages")));
+    assert!(cache_dir.join("scan").is_dir());
+
+    anvil(&cfg)
+        .args(["cache", "info"])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains(cache_dir.to_string_lossy().as_ref())
+                .and(predicate::str::contains("(ANVIL_CACHE_DIR)"))
+                .and(predicate::str::contains("1 entry"))
+                .and(predicate::str::contains("[current config]"))
+                .and(predicate::str::contains("3 packages")),
+        );
+
+    anvil(&cfg)
+        .args(["cache", "clear"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 1 cache file(s)"));
+
+    anvil(&cfg)
+        .args(["cache", "info"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 entries"));
+}
+
+#[test]
+fn cache_dir_from_config() {
+    let (dir, cfg) = setup_env();
+    let custom = dir.path().join("custom-cache");
+    let mut content = fs::read_to_string(&cfg).unwrap();
+    content.push_str(&format!("cache_dir: {}\n", custom.display()));
+    fs::write(&cfg, content).unwrap();
+
+    anvil(&cfg)
+        .env_remove("ANVIL_CACHE_DIR")
+        .args(["cache", "warm"])
+        .assert()
+        .success();
+    assert!(custom.join("scan").is_dir());
+
+    anvil(&cfg)
+        .env_remove("ANVIL_CACHE_DIR")
+        .args(["cache", "info"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(config)"));
+}
+
+#[test]
+fn cache_warm_matches_configured_paths() {
+    let (dir, cfg) = setup_env();
+    let mut content = fs::read_to_string(&cfg).unwrap();
+    content = content.replacen(
+        "package_paths:\n",
+        &format!("package_paths:\n  - {}\n", dir.path().join("unmounted").display()),
+        1,
+    );
+    fs::write(&cfg, content).unwrap();
+
+    // Missing configured paths are left for the offline fallback to report.
+    anvil(&cfg)
+        .args(["cache", "warm"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Skipping").not());
+    anvil(&cfg).args(["cache", "clear"]).assert().success();
+
+    // Other spellings of the configured path warm the same entry.
+    for spelling in ["./packages", "packages/"] {
+        anvil(&cfg)
+            .current_dir(dir.path())
+            .args(["cache", "warm", spelling])
+            .assert()
+            .success();
+    }
+    anvil(&cfg)
+        .args(["cache", "info"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 entry"))
+        .stdout(predicate::str::contains("[current config]"));
+}
+
+// ---- anvil index ----
+
+#[test]
+fn index_build_and_publish_updates_it() {
+    let (dir, cfg) = setup_env();
+    let pkg_dir = dir.path().join("packages");
+
+    anvil(&cfg)
+        .args(["index", "build", pkg_dir.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Indexed 3 packages"));
+    assert!(pkg_dir.join(".anvil-index.json").exists());
+    assert!(!pkg_dir.join(".anvil-index.lock").exists());
+
+    // A cold cache takes everything from the index.
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=info")
+        .args(["list"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("from the repository index"))
+        .stderr(predicate::str::contains("Re-read").not());
+
+    let src = dir.path().join("src/newtool");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("package.yaml"), "name: newtool\nversion: \"1.0\"\n").unwrap();
+    anvil(&cfg)
+        .args(["publish", pkg_dir.to_str().unwrap(), "--path", src.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Updated index").and(predicate::str::contains("1 entry re-read")));
+
+    let index = fs::read_to_string(pkg_dir.join(".anvil-index.json")).unwrap();
+    assert!(index.contains("newtool"));
+}
+
+// ---- resolve cache ----
+
+#[test]
+fn resolve_cache_reused_until_packages_change() {
+    let (dir, cfg) = setup_env();
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=info")
+        .args(["env", "python"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Using cached resolve").not());
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=info")
+        .args(["env", "python"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Using cached resolve"))
+        .stdout(predicate::str::contains("PYTHON_VERSION=3.11"));
+
+    // A new release in the family invalidates the entry.
+    let py312 = dir.path().join("packages/python/3.12");
+    fs::create_dir_all(&py312).unwrap();
+    fs::write(
+        py312.join("package.yaml"),
+        "name: python\nversion: \"3.12\"\nenvironment:\n  PYTHON_VERSION: \"3.12\"\n",
+    )
+    .unwrap();
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=info")
+        .args(["env", "python"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Using cached resolve").not())
+        .stdout(predicate::str::contains("PYTHON_VERSION=3.12"));
+}
+
+#[test]
+fn resolve_cache_keyed_by_config() {
+    let (_dir, cfg) = setup_env();
+
+    anvil(&cfg).args(["env", "maya-2024"]).assert().success();
+
+    let mut content = fs::read_to_string(&cfg).unwrap();
+    content.push_str("overrides:\n  maya:\n    environment:\n      MAYA_PATCHED: \"1\"\n");
+    fs::write(&cfg, content).unwrap();
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=info")
+        .args(["env", "maya-2024"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Using cached resolve").not())
+        .stdout(predicate::str::contains("MAYA_PATCHED=1"));
+}
+
+// ---- unreachable package paths ----
+
+#[test]
+fn unreachable_path_falls_back_to_cached_scan() {
+    let (dir, cfg) = setup_env();
+    anvil(&cfg).args(["list"]).assert().success();
+
+    // Simulate the share going away.
+    let pkg_dir = dir.path().join("packages");
+    fs::rename(&pkg_dir, dir.path().join("unmounted")).unwrap();
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=warn")
+        .args(["env", "maya-2024"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("is unreachable; using its cached scan"))
+        .stdout(predicate::str::contains("MAYA_VERSION=2024"));
+}
+
+#[test]
+fn unreachable_path_fallback_survives_config_edits() {
+    let (dir, cfg) = setup_env();
+    anvil(&cfg).args(["list"]).assert().success();
+    fs::rename(dir.path().join("packages"), dir.path().join("unmounted")).unwrap();
+
+    // The cached scan belongs to the path, not to the rest of the config.
+    let extra = dir.path().join("extra");
+    fs::create_dir_all(&extra).unwrap();
+    let mut content = fs::read_to_string(&cfg).unwrap();
+    content = content.replacen(
+        "package_paths:\n",
+        &format!("package_paths:\n  - {}\n", extra.display()),
+        1,
+    );
+    content.push_str("filters:\n  exclude:\n    - \"nuke*\"\n");
+    fs::write(&cfg, content).unwrap();
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=warn")
+        .args(["env", "maya-2024"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("is unreachable; using its cached scan"))
+        .stdout(predicate::str::contains("MAYA_VERSION=2024"));
+}
+
+#[test]
+fn unreachable_path_without_cache_is_skipped_with_warning() {
+    let (dir, cfg) = setup_env();
+    fs::remove_dir_all(dir.path().join("packages")).unwrap();
+
+    anvil(&cfg)
+        .env("RUST_LOG", "anvil=warn")
+        .args(["env", "maya-2024"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no cached scan"));
+}
+
+#[test]
+fn require_package_paths_makes_unreachable_path_fatal() {
+    let (dir, cfg) = setup_env();
+    anvil(&cfg).args(["list"]).assert().success();
+
+    let mut content = fs::read_to_string(&cfg).unwrap();
+    content.push_str("require_package_paths: true\n");
+    fs::write(&cfg, content).unwrap();
+    fs::rename(dir.path().join("packages"), dir.path().join("unmounted")).unwrap();
+
+    anvil(&cfg)
+        .args(["env", "maya-2024"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Package path unreachable"));
+}